@@ -2,6 +2,9 @@
 // https://docs.rs/build-data/0.1.3/build_data/
 
 fn main() {
+    println!("cargo::rustc-check-cfg=cfg(random_play)");
+    println!("cargo::rustc-check-cfg=cfg(load_test_func)");
+    println!("cargo::rustc-check-cfg=cfg(load_test_slew)");
     build_data::set_GIT_BRANCH();
     build_data::set_GIT_COMMIT();
     build_data::set_SOURCE_TIMESTAMP();
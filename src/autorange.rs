@@ -0,0 +1,162 @@
+// autorange.rs
+#![allow(dead_code)]
+
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// Opt-in range selection for the electronic load: the lowest range that still
+// fits the value gives the best resolution and accuracy.
+
+impl IRange {
+    pub fn for_curr(curr: f32) -> anyhow::Result<Self> {
+        [Self::I5A, Self::I30A]
+            .into_iter()
+            .find(|r| curr <= *r as u32 as f32)
+            .ok_or(anyhow!("Current {curr} exceeds all ranges"))
+    }
+}
+
+impl VRange {
+    pub fn for_volt(volt: f32) -> anyhow::Result<Self> {
+        [Self::V36V, Self::V150V]
+            .into_iter()
+            .find(|r| volt <= *r as u32 as f32)
+            .ok_or(anyhow!("Voltage {volt} exceeds all ranges"))
+    }
+}
+
+impl RRange {
+    pub fn for_res(res: f32) -> anyhow::Result<Self> {
+        [Self::Low, Self::Middle, Self::High, Self::Upper]
+            .into_iter()
+            .find(|r| {
+                let (min, max) = r.limits();
+                res >= min && res <= max
+            })
+            .ok_or(anyhow!("Resistance {res} outside all ranges"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoRange {
+    pub irange: IRange,
+    pub vrange: VRange,
+    // CR mode only
+    pub rrange: Option<RRange>,
+}
+
+impl fmt::Display for AutoRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}A {}V", self.irange, self.vrange)?;
+        if let Some(rrange) = self.rrange {
+            write!(f, " {rrange}")?;
+        }
+        Ok(())
+    }
+}
+
+impl AutoRange {
+    // pick the ranges for a level (A, V, W or ohms) given the expected source voltage
+    pub fn select(f: TranFunc, level: f32, volt_src: f32) -> anyhow::Result<Self> {
+        if volt_src <= 0.0 {
+            return Err(anyhow!("Source voltage {volt_src} must be positive"));
+        }
+        Ok(match f {
+            TranFunc::Curr => Self {
+                irange: IRange::for_curr(level)?,
+                vrange: VRange::for_volt(volt_src)?,
+                rrange: None,
+            },
+            // the current is set by the source, leave room for it
+            TranFunc::Volt => Self {
+                irange: IRange::I30A,
+                vrange: VRange::for_volt(level.max(volt_src))?,
+                rrange: None,
+            },
+            TranFunc::Powr => Self {
+                irange: IRange::for_curr(level / volt_src)?,
+                vrange: VRange::for_volt(volt_src)?,
+                rrange: None,
+            },
+            TranFunc::Res => Self {
+                irange: IRange::for_curr(volt_src / level)?,
+                vrange: VRange::for_volt(volt_src)?,
+                rrange: Some(RRange::for_res(level)?),
+            },
+        })
+    }
+}
+
+impl SDL1000X {
+    pub fn ranges_q(&mut self, f: TranFunc) -> anyhow::Result<AutoRange> {
+        Ok(AutoRange {
+            irange: IRange::from_str(self.lxi.req(format!(":{f}:IRANG?"))?.as_str())?,
+            vrange: VRange::from_str(self.lxi.req(format!(":{f}:VRANG?"))?.as_str())?,
+            rrange: match f {
+                TranFunc::Res => Some(self.res_rrange_q()?),
+                _ => None,
+            },
+        })
+    }
+
+    // Set the level in the best range for it. Ranges are only switched with the
+    // input off, with the input on a range change is an error.
+    pub fn level_auto(
+        &mut self,
+        f: TranFunc,
+        level: f32,
+        volt_src: f32,
+    ) -> anyhow::Result<AutoRange> {
+        let want = AutoRange::select(f, level, volt_src)?;
+        let have = self.ranges_q(f)?;
+        if want != have {
+            if self.input_q()? == PortState::On {
+                return Err(anyhow!("Cannot switch {f} range {have} -> {want} with input on"));
+            }
+            self.lxi.set_s(&format!(":{f}:IRANG"), &want.irange.to_string())?;
+            self.lxi.set_s(&format!(":{f}:VRANG"), &want.vrange.to_string())?;
+            if let Some(rrange) = want.rrange {
+                self.res_rrange(rrange)?;
+            }
+            info!("{name}: {f} range {have} -> {want}", name = self.lxi.name());
+        }
+        self.set_level(f, level)?;
+        Ok(want)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select() {
+        let r = AutoRange::select(TranFunc::Curr, 2.0, 12.0).unwrap();
+        assert_eq!((r.irange, r.vrange, r.rrange), (IRange::I5A, VRange::V36V, None));
+        let r = AutoRange::select(TranFunc::Curr, 10.0, 48.0).unwrap();
+        assert_eq!((r.irange, r.vrange), (IRange::I30A, VRange::V150V));
+        assert!(AutoRange::select(TranFunc::Curr, 31.0, 12.0).is_err());
+
+        let r = AutoRange::select(TranFunc::Volt, 40.0, 12.0).unwrap();
+        assert_eq!((r.irange, r.vrange), (IRange::I30A, VRange::V150V));
+
+        // 100 W at 12 V is 8.3 A
+        let r = AutoRange::select(TranFunc::Powr, 100.0, 12.0).unwrap();
+        assert_eq!((r.irange, r.vrange), (IRange::I30A, VRange::V36V));
+
+        // 12 V over 100 ohms is 0.12 A
+        let r = AutoRange::select(TranFunc::Res, 100.0, 12.0).unwrap();
+        assert_eq!(
+            (r.irange, r.vrange, r.rrange),
+            (IRange::I5A, VRange::V36V, Some(RRange::Middle))
+        );
+
+        assert!(AutoRange::select(TranFunc::Curr, 1.0, 0.0).is_err());
+        assert!(AutoRange::select(TranFunc::Volt, 200.0, 12.0).is_err());
+    }
+}
+
+// EOF
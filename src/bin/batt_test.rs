@@ -0,0 +1,101 @@
+// main.rs
+
+use std::{thread, time};
+
+use my_hacklab::*;
+
+#[derive(Clone, Debug, Default, Parser)]
+pub struct MyOpts {
+    #[command(flatten)]
+    c: OptsCommon,
+
+    #[arg(long)]
+    pub load: String,
+    // discharge current in A
+    #[arg(long)]
+    pub curr: f32,
+    // stop voltage in V
+    #[arg(long)]
+    pub stop_volt: f32,
+    // optional stop capacity in Ah
+    #[arg(long)]
+    pub stop_cap: Option<f32>,
+    // optional stop time in seconds
+    #[arg(long)]
+    pub stop_time: Option<u64>,
+    // progress logging interval in seconds
+    #[arg(long, default_value_t = 10)]
+    pub interval: u64,
+}
+
+fn main() -> anyhow::Result<()> {
+    let opts = MyOpts::parse();
+    opts.c.start_pgm(env!("CARGO_BIN_NAME"));
+    debug!("config: {opts:?}");
+
+    let mut load = SDL1000X::new("LOAD", &opts.load)?;
+    info!("Lab LOAD at {:?}", load.lxi.addr());
+
+    load.short_off()?;
+    load.input_off()?;
+    load.sense_on()?;
+    thread::sleep(time::Duration::new(1, 0));
+
+    let volt_initial = load.volt_m()?;
+    info!("Battery open circuit voltage: {volt_initial:.3}V");
+
+    let cfg = BattConfig {
+        func: BattFunc::Curr,
+        level: opts.curr,
+        irange: if opts.curr > IRange::I5A as u32 as f32 {
+            IRange::I30A
+        } else {
+            IRange::I5A
+        },
+        vrange: if volt_initial > VRange::V36V as u32 as f32 {
+            VRange::V150V
+        } else {
+            VRange::V36V
+        },
+        rrange: None,
+        stop_volt: Some(opts.stop_volt),
+        stop_cap: opts.stop_cap,
+        stop_time: opts.stop_time.map(time::Duration::from_secs),
+    };
+    load.batt_setup(&cfg)?;
+
+    info!("*** Starting discharge at {:.3}A down to {:.3}V", opts.curr, opts.stop_volt);
+    load.batt_start()?;
+
+    // the energy is integrated by the driver from these polls
+    let interval = time::Duration::from_secs(opts.interval);
+    loop {
+        thread::sleep(interval);
+        let p = load.batt_progress_q()?;
+        if !p.running {
+            break;
+        }
+        info!(
+            "Volt: {:.3}V Curr: {:.3}A Power: {:.2}W Capacity: {:.3}Ah Energy: {:.3}Wh Time: {}s",
+            p.volt,
+            p.curr,
+            p.powr,
+            p.result.capacity,
+            p.result.energy,
+            p.result.time.as_secs()
+        );
+    }
+
+    let res = load.batt_result_q()?;
+    load.batt_stop()?;
+    load.sense_off()?;
+    info!(
+        "*** Done. Capacity: {:.3}Ah Energy: {:.3}Wh Time: {}s",
+        res.capacity,
+        res.energy,
+        res.time.as_secs()
+    );
+
+    Ok(())
+}
+// EOF
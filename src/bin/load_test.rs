@@ -32,21 +32,21 @@ fn main() -> anyhow::Result<()> {
     //ld.verbose = true;
     info!("Lab LOAD at {:?}", load.lxi.addr());
 
-    load.short_off()?;
-    load.input_off()?;
-    load.sense_on()?;
+    load.profile_apply_verify(&LoadProfile {
+        func: TranFunc::Curr,
+        irange: IRange::I5A,
+        vrange: VRange::V36V,
+        rrange: None,
+        level: CURR_START,
+        slew_p: None,
+        slew_n: None,
+        sense: PortState::On,
+        von: None,
+    })?;
 
     info!("***");
     thread::sleep(time::Duration::new(1, 0));
 
-    load.func(sdl1000x::Func::Curr)?;
-    load.curr_irange(sdl1000x::IRange::I5A)?;
-    load.curr_vrange(sdl1000x::VRange::V36V)?;
-
-    load.lxi.req(":current:irange?")?;
-    load.lxi.req(":current:vrange?")?;
-
-    load.curr_curr(Curr::A(CURR_START))?;
     load.lxi.set_state(":input:state", PortState::On)?;
 
     info!("***");
@@ -63,6 +63,7 @@ fn main() -> anyhow::Result<()> {
         load.curr_curr(Curr::A(curr))?;
 
         thread::sleep(time::Duration::new(2, 0));
+        load.prot_check()?;
         load.res_m()?;
         load.curr_m()?;
         let pwr = load.powr_m()?;
@@ -129,6 +130,7 @@ fn steps_i(
 
         ld.curr_curr(Curr::A(i_now))?;
         thread::sleep(time::Duration::new(1, 0));
+        ld.prot_check()?;
         let v_now = ld.volt_m()?;
 
         // did we cross the threshold?
@@ -32,15 +32,22 @@ fn main() -> anyhow::Result<()> {
     pwr.lan_mask_q()?;
     pwr.lan_gw_q()?;
 
-    info!("PWR status:\n{:#?}", pwr.status_q()?);
+    let mut status = pwr.status_q()?;
+    info!("PWR status: {status}");
 
     pwr.lxi.v_off();
     thread::sleep(time::Duration::new(1, 0));
 
     loop {
-        let (curr1, curr2) = (pwr.curr_m(Ch::Ch1)?, pwr.curr_m(Ch::Ch2)?);
-        let (volt1, volt2) = (pwr.volt_m(Ch::Ch1)?, pwr.volt_m(Ch::Ch2)?);
-        let (pwr1, pwr2) = (pwr.powr_m(Ch::Ch1)?, pwr.powr_m(Ch::Ch2)?);
+        let new_status = pwr.status_q()?;
+        for change in status.diff(&new_status) {
+            info!("PWR status change: {change}");
+        }
+        status = new_status;
+
+        let (curr1, curr2) = (pwr.curr_m(PwrCh::Ch1)?, pwr.curr_m(PwrCh::Ch2)?);
+        let (volt1, volt2) = (pwr.volt_m(PwrCh::Ch1)?, pwr.volt_m(PwrCh::Ch2)?);
+        let (pwr1, pwr2) = (pwr.powr_m(PwrCh::Ch1)?, pwr.powr_m(PwrCh::Ch2)?);
         let volt = volt1 + volt2;
         let pwr = pwr1 + pwr2;
 
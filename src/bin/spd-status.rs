@@ -29,7 +29,8 @@ fn main() -> anyhow::Result<()> {
     pwr.lan_mask_q()?;
     pwr.lan_gw_q()?;
 
-    info!("PWR status:\n{:#?}", pwr.status_q()?);
+    let status = pwr.status_q()?;
+    info!("PWR status: {status} (0x{:04x})", status.to_u16());
 
     Ok(())
 }
@@ -46,29 +46,44 @@ fn main() -> anyhow::Result<()> {
     info!("LOAD lan_gw: {}", load.lan_gw_q()?);
 
     info!("LOAD setting up");
-    load.short_off()?;
-    load.input_off()?;
-    load.sense_on()?;
-    load.func(sdl1000x::Func::Curr)?;
-    load.curr_irange(IRange::I5A)?;
-    load.curr_vrange(VRange::V36V)?;
+    let profile = LoadProfile {
+        func: TranFunc::Curr,
+        irange: IRange::I5A,
+        vrange: VRange::V36V,
+        rrange: None,
+        level: 0.0,
+        slew_p: None,
+        slew_n: None,
+        sense: PortState::On,
+        von: None,
+    };
+    // there is no profile to read back in LED mode
+    match TranFunc::try_from(load.func_q()?) {
+        Ok(_) => {
+            for change in load.profile_q()?.diff(&profile) {
+                info!("LOAD profile change: {change}");
+            }
+        }
+        Err(e) => info!("LOAD profile not compared: {e}"),
+    }
+    load.profile_apply(&profile)?;
 
     info!("PWR setting up");
     pwr.output_independent()?;
-    pwr.output_off(Ch::Ch1)?;
-    pwr.output_off(Ch::Ch2)?;
-    pwr.output_off(Ch::Ch3)?;
-    pwr.wave_display(Ch::Ch1, PortState::Off)?;
-    pwr.wave_display(Ch::Ch2, PortState::Off)?;
+    pwr.output_off(PwrCh::Ch1)?;
+    pwr.output_off(PwrCh::Ch2)?;
+    pwr.output_off(PwrOut::Ch3)?;
+    pwr.wave_display(PwrCh::Ch1, PortState::Off)?;
+    pwr.wave_display(PwrCh::Ch2, PortState::Off)?;
 
     #[cfg(random_play)]
     {
         let mut p_volt: f32 = 4.250;
         let mut p_curr: f32 = 0.250;
         info!("PWR set ch1 volt={:.3} curr={:.3}, out=ON", p_volt, p_curr);
-        pwr.volt(Ch::Ch1, p_volt)?;
-        pwr.curr(Ch::Ch1, p_curr)?;
-        pwr.output_on(Ch::Ch1)?;
+        pwr.volt(PwrCh::Ch1, p_volt)?;
+        pwr.curr(PwrCh::Ch1, p_curr)?;
+        pwr.output_on(PwrCh::Ch1)?;
         info!("PWR status:\n{:#?}", pwr.status_q()?);
 
         info!("LOAD sense: {}", load.sense_q()?);
@@ -90,18 +105,18 @@ fn main() -> anyhow::Result<()> {
         info!("LOAD meas powr: {:.3}", load.powr_m()?);
         info!("LOAD meas res: {:.3}", load.res_m()?);
 
-        info!("PWR set volt: {:.3}", pwr.volt_q(Ch::Ch1)?);
-        info!("PWR set curr: {:.3}", pwr.curr_q(Ch::Ch1)?);
+        info!("PWR set volt: {:.3}", pwr.volt_q(PwrCh::Ch1)?);
+        info!("PWR set curr: {:.3}", pwr.curr_q(PwrCh::Ch1)?);
 
-        info!("PWR meas volt: {:.3}", pwr.volt_m(Ch::Ch1)?);
-        info!("PWR meas curr: {:.3}", pwr.curr_m(Ch::Ch1)?);
-        info!("PWR meas powr: {:.3}", pwr.powr_m(Ch::Ch1)?);
+        info!("PWR meas volt: {:.3}", pwr.volt_m(PwrCh::Ch1)?);
+        info!("PWR meas curr: {:.3}", pwr.curr_m(PwrCh::Ch1)?);
+        info!("PWR meas powr: {:.3}", pwr.powr_m(PwrCh::Ch1)?);
 
         p_volt = 8.500;
         p_curr = 0.500;
         info!("PWR set ch1 volt={:.3} curr={:.3}, out=ON", p_volt, p_curr);
-        pwr.volt(Ch::Ch1, p_volt)?;
-        pwr.curr(Ch::Ch1, p_curr)?;
+        pwr.volt(PwrCh::Ch1, p_volt)?;
+        pwr.curr(PwrCh::Ch1, p_curr)?;
 
         load.curr_curr(Curr::A(0.150))?;
 
@@ -114,19 +129,19 @@ fn main() -> anyhow::Result<()> {
         info!("LOAD meas res: {:.3}", load.res_m()?);
 
         info!("PWR status:\n{:#?}", pwr.status_q()?);
-        info!("PWR set volt: {:.3}", pwr.volt_q(Ch::Ch1)?);
-        info!("PWR set curr: {:.3}", pwr.curr_q(Ch::Ch1)?);
+        info!("PWR set volt: {:.3}", pwr.volt_q(PwrCh::Ch1)?);
+        info!("PWR set curr: {:.3}", pwr.curr_q(PwrCh::Ch1)?);
 
-        info!("PWR meas volt: {:.3}", pwr.volt_m(Ch::Ch1)?);
-        info!("PWR meas curr: {:.3}", pwr.curr_m(Ch::Ch1)?);
-        info!("PWR meas powr: {:.3}", pwr.powr_m(Ch::Ch1)?);
+        info!("PWR meas volt: {:.3}", pwr.volt_m(PwrCh::Ch1)?);
+        info!("PWR meas curr: {:.3}", pwr.curr_m(PwrCh::Ch1)?);
+        info!("PWR meas powr: {:.3}", pwr.powr_m(PwrCh::Ch1)?);
 
         info!("*** sleep 1");
         thread::sleep(time::Duration::new(1, 0));
 
         load.input_off()?;
         load.sense_off()?;
-        pwr.output_off(Ch::Ch1)?;
+        pwr.output_off(PwrCh::Ch1)?;
         info!("PWR status:\n{:#?}", pwr.status_q()?);
     }
 
@@ -0,0 +1,411 @@
+// dp800.rs
+#![allow(dead_code)]
+
+use std::{fmt, fmt::Display, net::ToSocketAddrs, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// https://www.rigolna.com/pdfs/manuals/DP800-Programming-Guide.pdf
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DpCh {
+    Ch1,
+    Ch2,
+    Ch3,
+}
+
+impl From<DpCh> for Ch {
+    fn from(c: DpCh) -> Self {
+        match c {
+            DpCh::Ch1 => Ch::Ch1,
+            DpCh::Ch2 => Ch::Ch2,
+            DpCh::Ch3 => Ch::Ch3,
+        }
+    }
+}
+
+impl Display for DpCh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Ch::from(*self).fmt(f)
+    }
+}
+
+impl DpCh {
+    // SOURce1..3
+    fn num(&self) -> u8 {
+        match *self {
+            Self::Ch1 => 1,
+            Self::Ch2 => 2,
+            Self::Ch3 => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DpMode {
+    CV,
+    CC,
+    // unregulated
+    UR,
+}
+
+impl Display for DpMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::CV => "CV",
+            Self::CC => "CC",
+            Self::UR => "UR",
+        })
+    }
+}
+
+impl FromStr for DpMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CV" => Ok(Self::CV),
+            "CC" => Ok(Self::CC),
+            "UR" => Ok(Self::UR),
+            x => Err(anyhow!("Unknown output mode {x}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DP800Model {
+    DP832,
+    DP832A,
+    DP821,
+}
+
+impl DP800Model {
+    // (volt_max, curr_max) per channel
+    pub fn limits(&self) -> &'static [(f32, f32)] {
+        match *self {
+            Self::DP832 | Self::DP832A => &[(30.0, 3.0), (30.0, 3.0), (5.0, 3.0)],
+            Self::DP821 => &[(60.0, 1.0), (8.0, 10.0)],
+        }
+    }
+}
+
+impl Display for DP800Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::DP832 => "DP832",
+            Self::DP832A => "DP832A",
+            Self::DP821 => "DP821",
+        })
+    }
+}
+
+impl FromStr for DP800Model {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "DP832" => Ok(Self::DP832),
+            "DP832A" => Ok(Self::DP832A),
+            "DP821" => Ok(Self::DP821),
+            x => Err(anyhow!("Unknown model {x}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DpProt {
+    pub state: PortState,
+    // V for OVP, A for OCP
+    pub level: f32,
+    pub tripped: bool,
+}
+
+pub struct DP800 {
+    pub lxi: StdLxi,
+    pub model: DP800Model,
+}
+
+impl DP800 {
+    pub fn new<S, H>(name: S, host: H) -> anyhow::Result<Self>
+        where
+            S: AsRef<str>,
+            H: fmt::Display + AsRef<str> + ToSocketAddrs,
+            Self: Sized,
+    {
+        let mut pwr = Self {
+            lxi: StdLxi::new(name, host)?,
+            model: DP800Model::DP832,
+        };
+        pwr.detect_model()?;
+        Ok(pwr)
+    }
+
+    pub fn detect_model(&mut self) -> anyhow::Result<DP800Model> {
+        let idn = self.idn_q()?;
+        // the channel limits depend on the model, do not guess
+        self.model = idn_model(&idn)?;
+        Ok(self.model)
+    }
+
+    pub fn channels(&self) -> Vec<DpCh> {
+        [DpCh::Ch1, DpCh::Ch2, DpCh::Ch3]
+            .into_iter()
+            .take(self.model.limits().len())
+            .collect()
+    }
+    // (volt_max, curr_max) of the channel, error if the model does not have it
+    pub fn limits(&self, c: DpCh) -> anyhow::Result<(f32, f32)> {
+        match self.model.limits().get(c.num() as usize - 1) {
+            Some(lim) => Ok(*lim),
+            None => Err(anyhow!("{} does not have {c}", self.model)),
+        }
+    }
+
+    pub fn idn_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req("*IDN?")
+    }
+    pub fn version_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req(":SYST:VERS?")
+    }
+    pub fn error_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req(":SYST:ERR?")
+    }
+    pub fn lan_addr_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req(":SYST:COMM:LAN:IPAD?")
+    }
+    pub fn lan_mask_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req(":SYST:COMM:LAN:SMASK?")
+    }
+    pub fn lan_gw_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req(":SYST:COMM:LAN:GAT?")
+    }
+
+    pub fn meas_q(&mut self, c: DpCh, m: PwrMeas) -> anyhow::Result<f32> {
+        self.limits(c)?;
+        let m = match m {
+            PwrMeas::Volt => "VOLT",
+            PwrMeas::Curr => "CURR",
+            PwrMeas::Powr => "POWE",
+        };
+        self.lxi.get_f(format!(":MEAS:{m}? {c}"))
+    }
+    pub fn volt_m(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.meas_q(c, PwrMeas::Volt)
+    }
+    pub fn curr_m(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.meas_q(c, PwrMeas::Curr)
+    }
+    pub fn powr_m(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.meas_q(c, PwrMeas::Powr)
+    }
+
+    pub fn volt(&mut self, c: DpCh, v: f32) -> anyhow::Result<f32> {
+        let (volt_max, _) = self.limits(c)?;
+        level_check("Voltage", v, volt_max)?;
+        self.lxi.set_f(format!(":SOUR{}:VOLT", c.num()), v)
+    }
+    pub fn curr(&mut self, c: DpCh, v: f32) -> anyhow::Result<f32> {
+        let (_, curr_max) = self.limits(c)?;
+        level_check("Current", v, curr_max)?;
+        self.lxi.set_f(format!(":SOUR{}:CURR", c.num()), v)
+    }
+    pub fn volt_q(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.limits(c)?;
+        self.lxi.get_f(format!(":SOUR{}:VOLT?", c.num()))
+    }
+    pub fn curr_q(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.limits(c)?;
+        self.lxi.get_f(format!(":SOUR{}:CURR?", c.num()))
+    }
+
+    pub fn output_state(&mut self, c: DpCh, state: PortState) -> anyhow::Result<()> {
+        self.limits(c)?;
+        self.lxi.send(format!(":OUTP {c},{state}"))
+    }
+    pub fn output_on(&mut self, c: DpCh) -> anyhow::Result<()> {
+        self.output_state(c, PortState::On)
+    }
+    pub fn output_off(&mut self, c: DpCh) -> anyhow::Result<()> {
+        self.output_state(c, PortState::Off)
+    }
+    pub fn output_q(&mut self, c: DpCh) -> anyhow::Result<PortState> {
+        self.limits(c)?;
+        self.lxi.get_state(format!(":OUTP? {c}"))
+    }
+    pub fn mode_q(&mut self, c: DpCh) -> anyhow::Result<DpMode> {
+        self.limits(c)?;
+        DpMode::from_str(self.lxi.req(format!(":OUTP:MODE? {c}"))?.trim())
+    }
+
+    // CH1 and CH2 of the DP832 can track each other
+    fn track_check(&self, c: DpCh) -> anyhow::Result<()> {
+        match c {
+            DpCh::Ch1 | DpCh::Ch2 if self.model != DP800Model::DP821 => Ok(()),
+            _ => Err(anyhow!("{} cannot track {c}", self.model)),
+        }
+    }
+    pub fn track(&mut self, c: DpCh, state: PortState) -> anyhow::Result<()> {
+        self.track_check(c)?;
+        self.lxi.send(format!(":OUTP:TRACK {c},{state}"))
+    }
+    pub fn track_q(&mut self, c: DpCh) -> anyhow::Result<PortState> {
+        self.track_check(c)?;
+        self.lxi.get_state(format!(":OUTP:TRACK? {c}"))
+    }
+
+    // Hardware OVP/OCP, the output is switched off when the level is exceeded
+    pub fn ovp(&mut self, c: DpCh, state: PortState, level: f32) -> anyhow::Result<()> {
+        let (volt_max, _) = self.limits(c)?;
+        // the protection level may be set a bit above the range
+        level_check("OVP", level, volt_max * 1.1)?;
+        self.lxi.set_f(format!(":SOUR{}:VOLT:PROT", c.num()), level)?;
+        self.lxi.set_state(format!(":SOUR{}:VOLT:PROT:STAT", c.num()), state)?;
+        Ok(())
+    }
+    pub fn ovp_q(&mut self, c: DpCh) -> anyhow::Result<DpProt> {
+        self.prot_q(c, "VOLT")
+    }
+    pub fn ovp_clear(&mut self, c: DpCh) -> anyhow::Result<()> {
+        self.limits(c)?;
+        self.lxi.send(format!(":SOUR{}:VOLT:PROT:CLE", c.num()))
+    }
+    pub fn ocp(&mut self, c: DpCh, state: PortState, level: f32) -> anyhow::Result<()> {
+        let (_, curr_max) = self.limits(c)?;
+        level_check("OCP", level, curr_max * 1.1)?;
+        self.lxi.set_f(format!(":SOUR{}:CURR:PROT", c.num()), level)?;
+        self.lxi.set_state(format!(":SOUR{}:CURR:PROT:STAT", c.num()), state)?;
+        Ok(())
+    }
+    pub fn ocp_q(&mut self, c: DpCh) -> anyhow::Result<DpProt> {
+        self.prot_q(c, "CURR")
+    }
+    pub fn ocp_clear(&mut self, c: DpCh) -> anyhow::Result<()> {
+        self.limits(c)?;
+        self.lxi.send(format!(":SOUR{}:CURR:PROT:CLE", c.num()))
+    }
+    fn prot_q(&mut self, c: DpCh, subsys: &str) -> anyhow::Result<DpProt> {
+        self.limits(c)?;
+        let n = c.num();
+        // the trip query answers YES or NO
+        let tripped = match self.lxi.req(format!(":SOUR{n}:{subsys}:PROT:TRIP?"))?.trim() {
+            "YES" => true,
+            "NO" => false,
+            r => return Err(anyhow!("Invalid {subsys} trip state {r}")),
+        };
+        Ok(DpProt {
+            state: self.lxi.get_state(format!(":SOUR{n}:{subsys}:PROT:STAT?"))?,
+            level: self.lxi.get_f(format!(":SOUR{n}:{subsys}:PROT?"))?,
+            tripped,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+
+    // A DP800 on localhost that answers a few queries and records everything it gets
+    fn simulator(model: &str) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let idn = format!("RIGOL TECHNOLOGIES,{model},DP8C123456789,00.01.14");
+        let cmds = log.clone();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut out = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                let cmd = line.trim().to_string();
+                let resp = match cmd.as_str() {
+                    "*IDN?" => Some(idn.as_str()),
+                    ":SOUR1:VOLT?" => Some("5.000"),
+                    ":MEAS:VOLT? CH1" => Some("4.998"),
+                    // CH1 OVP has tripped
+                    ":SOUR1:VOLT:PROT:STAT?" => Some("ON"),
+                    ":SOUR1:VOLT:PROT?" => Some("13.200"),
+                    ":SOUR1:VOLT:PROT:TRIP?" => Some("YES"),
+                    c if c.ends_with(":PROT:TRIP?") => Some("NO"),
+                    c if c.contains('?') => Some("0"),
+                    _ => None,
+                };
+                cmds.lock().unwrap().push(cmd);
+                if let Some(resp) = resp {
+                    out.write_all(format!("{resp}\n").as_bytes()).unwrap();
+                }
+            }
+        });
+        (addr, log)
+    }
+
+    #[test]
+    fn dp832() {
+        let (addr, log) = simulator("DP832");
+        let mut pwr = DP800::new("DP", addr).unwrap();
+        assert_eq!(pwr.model, DP800Model::DP832);
+        assert_eq!(pwr.channels(), [DpCh::Ch1, DpCh::Ch2, DpCh::Ch3]);
+
+        assert_eq!(pwr.volt_q(DpCh::Ch1).unwrap(), 5.0);
+        assert_eq!(pwr.meas_q(DpCh::Ch1, PwrMeas::Volt).unwrap(), 4.998);
+
+        assert!(pwr.volt(DpCh::Ch1, 12.0).is_ok());
+        assert!(pwr.volt(DpCh::Ch1, 30.5).is_err());
+        // CH3 is the 5 V channel
+        assert!(pwr.volt(DpCh::Ch3, 6.0).is_err());
+        assert!(pwr.curr(DpCh::Ch2, 3.0).is_ok());
+        assert!(pwr.curr(DpCh::Ch2, 3.1).is_err());
+        assert!(pwr.volt(DpCh::Ch2, f32::NAN).is_err());
+        assert!(pwr.track(DpCh::Ch1, PortState::On).is_ok());
+        assert!(pwr.track(DpCh::Ch3, PortState::On).is_err());
+
+        let ovp = pwr.ovp_q(DpCh::Ch1).unwrap();
+        assert_eq!(
+            ovp,
+            DpProt {
+                state: PortState::On,
+                level: 13.2,
+                tripped: true,
+            }
+        );
+        assert!(!pwr.ocp_q(DpCh::Ch1).unwrap().tripped);
+        assert!(!pwr.ovp_q(DpCh::Ch2).unwrap().tripped);
+
+        // only the valid settings reached the instrument
+        pwr.idn_q().unwrap();
+        let cmds = log.lock().unwrap();
+        let sent = cmds.iter().filter(|c| !c.contains('?')).collect::<Vec<_>>();
+        assert_eq!(sent, [":SOUR1:VOLT 12", ":SOUR2:CURR 3", ":OUTP:TRACK CH1,ON"]);
+    }
+
+    #[test]
+    fn dp821() {
+        let (addr, _log) = simulator("DP821");
+        let mut pwr = DP800::new("DP", addr).unwrap();
+        assert_eq!(pwr.model, DP800Model::DP821);
+        assert_eq!(pwr.channels(), [DpCh::Ch1, DpCh::Ch2]);
+
+        assert!(pwr.volt(DpCh::Ch1, 60.0).is_ok());
+        assert!(pwr.curr(DpCh::Ch1, 1.5).is_err());
+        assert!(pwr.curr(DpCh::Ch2, 10.0).is_ok());
+        assert!(pwr.volt(DpCh::Ch2, 9.0).is_err());
+        assert!(pwr.volt(DpCh::Ch3, 1.0).is_err());
+        assert!(pwr.meas_q(DpCh::Ch3, PwrMeas::Volt).is_err());
+        assert!(pwr.track(DpCh::Ch1, PortState::On).is_err());
+        assert!(pwr.track_q(DpCh::Ch1).is_err());
+    }
+
+    #[test]
+    fn unknown_model() {
+        let (addr, _log) = simulator("DP711");
+        assert!(DP800::new("DP", addr).is_err());
+    }
+}
+
+// EOF
@@ -0,0 +1,182 @@
+// instrument.rs
+#![allow(dead_code)]
+
+use std::{
+    fmt,
+    io::{Read, Write},
+};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// Instrument classes, so that sweeps and monitors can be written once
+// and run against any supported supply or load.
+
+pub trait PowerSupply {
+    type Channel: Copy + fmt::Display;
+
+    fn name(&self) -> &str;
+    // the adjustable and measurable channels
+    fn channels(&self) -> Vec<Self::Channel>;
+    fn set_volt(&mut self, c: Self::Channel, v: f32) -> anyhow::Result<()>;
+    fn set_curr(&mut self, c: Self::Channel, v: f32) -> anyhow::Result<()>;
+    fn set_output(&mut self, c: Self::Channel, state: PortState) -> anyhow::Result<()>;
+    fn meas_volt(&mut self, c: Self::Channel) -> anyhow::Result<f32>;
+    fn meas_curr(&mut self, c: Self::Channel) -> anyhow::Result<f32>;
+    fn meas_powr(&mut self, c: Self::Channel) -> anyhow::Result<f32> {
+        Ok(self.meas_volt(c)? * self.meas_curr(c)?)
+    }
+    fn reg_mode(&mut self, c: Self::Channel) -> anyhow::Result<PwrChannelMode>;
+}
+
+pub trait ElectronicLoad {
+    fn name(&self) -> &str;
+    fn set_func(&mut self, f: TranFunc) -> anyhow::Result<()>;
+    // A, V, W or ohms depending on the function
+    fn set_level(&mut self, f: TranFunc, level: f32) -> anyhow::Result<()>;
+    fn set_input(&mut self, state: PortState) -> anyhow::Result<()>;
+    fn meas_volt(&mut self) -> anyhow::Result<f32>;
+    fn meas_curr(&mut self) -> anyhow::Result<f32>;
+    fn meas_powr(&mut self) -> anyhow::Result<f32> {
+        Ok(self.meas_volt()? * self.meas_curr()?)
+    }
+}
+
+impl PowerSupply for SPD3303X {
+    type Channel = PwrCh;
+
+    fn name(&self) -> &str {
+        self.lxi.name()
+    }
+    fn channels(&self) -> Vec<PwrCh> {
+        vec![PwrCh::Ch1, PwrCh::Ch2]
+    }
+    fn set_volt(&mut self, c: PwrCh, v: f32) -> anyhow::Result<()> {
+        self.volt(c, v)?;
+        Ok(())
+    }
+    fn set_curr(&mut self, c: PwrCh, v: f32) -> anyhow::Result<()> {
+        self.curr(c, v)?;
+        Ok(())
+    }
+    fn set_output(&mut self, c: PwrCh, state: PortState) -> anyhow::Result<()> {
+        self.output_state(c, state)
+    }
+    fn meas_volt(&mut self, c: PwrCh) -> anyhow::Result<f32> {
+        self.volt_m(c)
+    }
+    fn meas_curr(&mut self, c: PwrCh) -> anyhow::Result<f32> {
+        self.curr_m(c)
+    }
+    fn meas_powr(&mut self, c: PwrCh) -> anyhow::Result<f32> {
+        self.powr_m(c)
+    }
+    fn reg_mode(&mut self, c: PwrCh) -> anyhow::Result<PwrChannelMode> {
+        Ok(self.status_q()?.mode(c))
+    }
+}
+
+impl PowerSupply for DP800 {
+    type Channel = DpCh;
+
+    fn name(&self) -> &str {
+        self.lxi.name()
+    }
+    fn channels(&self) -> Vec<DpCh> {
+        DP800::channels(self)
+    }
+    fn set_volt(&mut self, c: DpCh, v: f32) -> anyhow::Result<()> {
+        self.volt(c, v)?;
+        Ok(())
+    }
+    fn set_curr(&mut self, c: DpCh, v: f32) -> anyhow::Result<()> {
+        self.curr(c, v)?;
+        Ok(())
+    }
+    fn set_output(&mut self, c: DpCh, state: PortState) -> anyhow::Result<()> {
+        self.output_state(c, state)
+    }
+    fn meas_volt(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.volt_m(c)
+    }
+    fn meas_curr(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.curr_m(c)
+    }
+    fn meas_powr(&mut self, c: DpCh) -> anyhow::Result<f32> {
+        self.powr_m(c)
+    }
+    fn reg_mode(&mut self, c: DpCh) -> anyhow::Result<PwrChannelMode> {
+        match self.mode_q(c)? {
+            DpMode::CV => Ok(PwrChannelMode::CV),
+            DpMode::CC => Ok(PwrChannelMode::CC),
+            DpMode::UR => Err(anyhow!("{c} is unregulated")),
+        }
+    }
+}
+
+impl<P: Read + Write> PowerSupply for KA3005P<P> {
+    type Channel = Ch;
+
+    fn name(&self) -> &str {
+        KA3005P::name(self)
+    }
+    fn channels(&self) -> Vec<Ch> {
+        vec![Ch::Ch1]
+    }
+    fn set_volt(&mut self, c: Ch, v: f32) -> anyhow::Result<()> {
+        self.volt(c, v)?;
+        Ok(())
+    }
+    fn set_curr(&mut self, c: Ch, v: f32) -> anyhow::Result<()> {
+        self.curr(c, v)?;
+        Ok(())
+    }
+    // the KA3005P has one output switch
+    fn set_output(&mut self, c: Ch, state: PortState) -> anyhow::Result<()> {
+        Self::ch_check(c)?;
+        self.output_state(state)
+    }
+    fn meas_volt(&mut self, c: Ch) -> anyhow::Result<f32> {
+        self.volt_m(c)
+    }
+    fn meas_curr(&mut self, c: Ch) -> anyhow::Result<f32> {
+        self.curr_m(c)
+    }
+    fn reg_mode(&mut self, _c: Ch) -> anyhow::Result<PwrChannelMode> {
+        Ok(self.status_q()?.mode)
+    }
+}
+
+impl ElectronicLoad for SDL1000X {
+    fn name(&self) -> &str {
+        self.lxi.name()
+    }
+    fn set_func(&mut self, f: TranFunc) -> anyhow::Result<()> {
+        self.func(f.into())?;
+        Ok(())
+    }
+    fn set_level(&mut self, f: TranFunc, level: f32) -> anyhow::Result<()> {
+        match f {
+            TranFunc::Curr => self.curr_curr(Curr::A(level)),
+            TranFunc::Volt => self.volt_volt(Volt::V(level)),
+            TranFunc::Powr => self.powr_powr(Powr::W(level)),
+            TranFunc::Res => self.res_res(Res::Ohm(level)),
+        }
+    }
+    fn set_input(&mut self, state: PortState) -> anyhow::Result<()> {
+        self.input(state)?;
+        Ok(())
+    }
+    fn meas_volt(&mut self) -> anyhow::Result<f32> {
+        self.volt_m()
+    }
+    fn meas_curr(&mut self) -> anyhow::Result<f32> {
+        self.curr_m()
+    }
+    fn meas_powr(&mut self) -> anyhow::Result<f32> {
+        self.powr_m()
+    }
+}
+
+// EOF
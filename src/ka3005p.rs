@@ -0,0 +1,410 @@
+// ka3005p.rs
+#![allow(dead_code)]
+
+use std::{
+    fmt,
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    path::Path,
+    thread, time,
+};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// Korad KA3005P and its clones (RND 320-KA3005P, Tenma 72-2540 etc.)
+// The protocol is SCPI-like but has no terminators at all, the supply relies on
+// a pause between commands and the replies have a fixed length.
+// The tty has to be in raw mode with a read timeout before opening it, e.g.
+// stty -F /dev/ttyACM0 9600 raw -echo min 0 time 10
+
+const VOLT_MAX: f32 = 30.0;
+const CURR_MAX: f32 = 5.0;
+// the supply ignores commands that follow each other too closely
+const CMD_DELAY: time::Duration = time::Duration::from_millis(50);
+// "12.00" and "1.000"
+const VALUE_LEN: usize = 5;
+const IDN_LEN_MAX: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KaStatusChange {
+    Output(PortState),
+    Mode(PwrChannelMode, PwrChannelMode),
+    Beep(PortState),
+    Ocp(PortState),
+    Ovp(PortState),
+}
+
+impl Display for KaStatusChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Output(st) => write!(f, "{} output {st}", Ch::Ch1),
+            Self::Mode(old, new) => write!(f, "{} {old}->{new}", Ch::Ch1),
+            Self::Beep(st) => write!(f, "beep {st}"),
+            Self::Ocp(st) => write!(f, "OCP {st}"),
+            Self::Ovp(st) => write!(f, "OVP {st}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KA3005PStatus {
+    pub output: PortState,
+    pub mode: PwrChannelMode,
+    pub beep: PortState,
+    pub ocp: PortState,
+    pub ovp: PortState,
+}
+
+impl KA3005PStatus {
+    // bit 0 CH1 CC/CV, bits 1-3 unused on the single channel models,
+    // bit 4 beep, bit 5 OCP, bit 6 output, bit 7 OVP
+    pub fn from_u8(st: u8) -> Self {
+        let state = |n: u8| {
+            if st & (1 << n) == 0 {
+                PortState::Off
+            } else {
+                PortState::On
+            }
+        };
+        Self {
+            output: state(6),
+            mode: if st & 1 == 0 {
+                PwrChannelMode::CC
+            } else {
+                PwrChannelMode::CV
+            },
+            beep: state(4),
+            ocp: state(5),
+            ovp: state(7),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        (bit(self.mode == PwrChannelMode::CV, 0)
+            | bit(self.beep == PortState::On, 4)
+            | bit(self.ocp == PortState::On, 5)
+            | bit(self.output == PortState::On, 6)
+            | bit(self.ovp == PortState::On, 7)) as u8
+    }
+
+    // the one channel's output and CV/CC mode, then the beep and OCP/OVP switches
+    pub fn diff(&self, new: &Self) -> Vec<KaStatusChange> {
+        let mut changes = Vec::new();
+        if self.output != new.output {
+            changes.push(KaStatusChange::Output(new.output));
+        }
+        if self.mode != new.mode {
+            changes.push(KaStatusChange::Mode(self.mode, new.mode));
+        }
+        if self.beep != new.beep {
+            changes.push(KaStatusChange::Beep(new.beep));
+        }
+        if self.ocp != new.ocp {
+            changes.push(KaStatusChange::Ocp(new.ocp));
+        }
+        if self.ovp != new.ovp {
+            changes.push(KaStatusChange::Ovp(new.ovp));
+        }
+        changes
+    }
+}
+
+impl Display for KA3005PStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}, OCP {}, OVP {}, beep {}",
+            Ch::Ch1,
+            self.output,
+            self.mode,
+            self.ocp,
+            self.ovp,
+            self.beep,
+        )
+    }
+}
+
+pub struct KA3005P<P: Read + Write = File> {
+    pub name: String,
+    pub port: P,
+    pub v: bool,
+}
+
+impl KA3005P<File> {
+    pub fn open<S, T>(name: S, tty: T) -> anyhow::Result<Self>
+        where
+            S: AsRef<str>,
+            T: AsRef<Path>,
+    {
+        let tty = tty.as_ref();
+        debug!("Opening {tty:?}...");
+        let port = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(tty)
+            .map_err(|e| anyhow!("Cannot open {tty:?}: {e}"))?;
+        Ok(Self::new(name, port))
+    }
+}
+
+impl<P: Read + Write> KA3005P<P> {
+    pub fn new<S: AsRef<str>>(name: S, port: P) -> Self {
+        Self {
+            name: name.as_ref().to_owned(),
+            port,
+            v: false,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn v_on(&mut self) {
+        self.v = true;
+    }
+    pub fn v_off(&mut self) {
+        self.v = false;
+    }
+
+    pub fn send<S: AsRef<str> + Display>(&mut self, s: S) -> anyhow::Result<()> {
+        if self.v {
+            info!("Send: {name} <-- {s}", name = self.name);
+        }
+        self.port.write_all(s.as_ref().as_bytes())?;
+        self.port.flush()?;
+        thread::sleep(CMD_DELAY);
+        Ok(())
+    }
+
+    // fixed length reply, a short read means the supply did not answer
+    fn req_n<S: AsRef<str> + Display>(&mut self, s: S, n: usize) -> anyhow::Result<Vec<u8>> {
+        self.send(s.as_ref())?;
+        let mut buf = vec![0; n];
+        self.port
+            .read_exact(&mut buf)
+            .map_err(|e| anyhow!("{s}: no reply from {}: {e}", self.name))?;
+        if self.v {
+            info!("{s} --> {} --> {buf:?}", self.name);
+        }
+        Ok(buf)
+    }
+
+    fn get_f<S: AsRef<str> + Display>(&mut self, s: S) -> anyhow::Result<f32> {
+        let r = self.req_n(s, VALUE_LEN)?;
+        let r = String::from_utf8_lossy(&r);
+        r.trim()
+            .parse::<f32>()
+            .map_err(|e| anyhow!("Cannot parse {r}: {e}"))
+    }
+
+    // only CH1 on the single channel models
+    pub(crate) fn ch_check(c: Ch) -> anyhow::Result<u8> {
+        match c {
+            Ch::Ch1 => Ok(1),
+            c => Err(anyhow!("KA3005P does not have {c}")),
+        }
+    }
+
+    // "KORAD KA3005P V5.8 SN:03379314", the length varies by firmware
+    // and the tty may hand it over in pieces, read until the timeout
+    pub fn idn_q(&mut self) -> anyhow::Result<String> {
+        self.send("*IDN?")?;
+        let mut buf = [0; IDN_LEN_MAX];
+        let mut n = 0;
+        while n < buf.len() {
+            match self.port.read(&mut buf[n..])? {
+                0 => break,
+                r => n += r,
+            }
+        }
+        let idn = String::from_utf8_lossy(&buf[..n]).trim().to_owned();
+        if self.v {
+            info!("*IDN? --> {} --> {idn}", self.name);
+        }
+        Ok(idn)
+    }
+
+    pub fn volt(&mut self, c: Ch, v: f32) -> anyhow::Result<f32> {
+        let n = Self::ch_check(c)?;
+        level_check("Voltage", v, VOLT_MAX)?;
+        self.send(format!("VSET{n}:{v:05.2}"))?;
+        Ok(v)
+    }
+    pub fn curr(&mut self, c: Ch, v: f32) -> anyhow::Result<f32> {
+        let n = Self::ch_check(c)?;
+        level_check("Current", v, CURR_MAX)?;
+        self.send(format!("ISET{n}:{v:05.3}"))?;
+        Ok(v)
+    }
+    pub fn volt_q(&mut self, c: Ch) -> anyhow::Result<f32> {
+        let n = Self::ch_check(c)?;
+        self.get_f(format!("VSET{n}?"))
+    }
+    pub fn curr_q(&mut self, c: Ch) -> anyhow::Result<f32> {
+        let n = Self::ch_check(c)?;
+        self.get_f(format!("ISET{n}?"))
+    }
+
+    pub fn volt_m(&mut self, c: Ch) -> anyhow::Result<f32> {
+        let n = Self::ch_check(c)?;
+        self.get_f(format!("VOUT{n}?"))
+    }
+    pub fn curr_m(&mut self, c: Ch) -> anyhow::Result<f32> {
+        let n = Self::ch_check(c)?;
+        self.get_f(format!("IOUT{n}?"))
+    }
+    pub fn powr_m(&mut self, c: Ch) -> anyhow::Result<f32> {
+        Ok(self.volt_m(c)? * self.curr_m(c)?)
+    }
+
+    pub fn status_q(&mut self) -> anyhow::Result<KA3005PStatus> {
+        let st = self.req_n("STATUS?", 1)?;
+        Ok(KA3005PStatus::from_u8(st[0]))
+    }
+
+    // there is a single output switch for all channels
+    pub fn output_state(&mut self, state: PortState) -> anyhow::Result<()> {
+        self.send(Self::state_cmd("OUT", state))
+    }
+    pub fn output_on(&mut self) -> anyhow::Result<()> {
+        self.output_state(PortState::On)
+    }
+    pub fn output_off(&mut self) -> anyhow::Result<()> {
+        self.output_state(PortState::Off)
+    }
+    pub fn output_q(&mut self) -> anyhow::Result<PortState> {
+        Ok(self.status_q()?.output)
+    }
+
+    // the protection levels follow the set voltage and current
+    pub fn ocp(&mut self, state: PortState) -> anyhow::Result<()> {
+        self.send(Self::state_cmd("OCP", state))
+    }
+    pub fn ovp(&mut self, state: PortState) -> anyhow::Result<()> {
+        self.send(Self::state_cmd("OVP", state))
+    }
+    pub fn beep(&mut self, state: PortState) -> anyhow::Result<()> {
+        self.send(Self::state_cmd("BEEP", state))
+    }
+
+    // memories M1..M5 on the front panel
+    pub fn save(&mut self, mem: u8) -> anyhow::Result<()> {
+        Self::mem_check(mem)?;
+        self.send(format!("SAV{mem}"))
+    }
+    pub fn recall(&mut self, mem: u8) -> anyhow::Result<()> {
+        Self::mem_check(mem)?;
+        self.send(format!("RCL{mem}"))
+    }
+
+    fn mem_check(mem: u8) -> anyhow::Result<()> {
+        if (1..=5).contains(&mem) {
+            Ok(())
+        } else {
+            Err(anyhow!("Invalid memory {mem}, must be 1..5"))
+        }
+    }
+    fn state_cmd(cmd: &str, state: PortState) -> String {
+        match state {
+            PortState::On => format!("{cmd}1"),
+            PortState::Off => format!("{cmd}0"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    // hands out the reply a few bytes at a time, then times out with 0
+    struct ChunkPort {
+        reply: Vec<u8>,
+        pos: usize,
+        sent: Vec<u8>,
+    }
+
+    impl Read for ChunkPort {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(4).min(self.reply.len() - self.pos);
+            buf[..n].copy_from_slice(&self.reply[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    impl Write for ChunkPort {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn supply(reply: &str) -> KA3005P<ChunkPort> {
+        KA3005P::new(
+            "KA",
+            ChunkPort {
+                reply: reply.as_bytes().to_vec(),
+                pos: 0,
+                sent: Vec::new(),
+            },
+        )
+    }
+
+    #[test]
+    fn status_round_trip() {
+        // bits 1-3 are not used
+        for st in (0..=u8::MAX).filter(|st| st & 0b1110 == 0) {
+            assert_eq!(KA3005PStatus::from_u8(st).to_u8(), st);
+        }
+        let s = KA3005PStatus::from_u8(0b0101_0001);
+        assert_eq!(s.output, PortState::On);
+        assert_eq!(s.mode, PwrChannelMode::CV);
+        assert_eq!(s.beep, PortState::On);
+        assert_eq!((s.ocp, s.ovp), (PortState::Off, PortState::Off));
+    }
+
+    #[test]
+    fn status_diff() {
+        // output on in CV, beep on
+        let old = KA3005PStatus::from_u8(0b0101_0001);
+        assert!(old.diff(&old).is_empty());
+        // current limit reached, OVP on
+        let new = KA3005PStatus::from_u8(0b1101_0000);
+        let changes = old.diff(&new);
+        assert_eq!(
+            changes,
+            [
+                KaStatusChange::Mode(PwrChannelMode::CV, PwrChannelMode::CC),
+                KaStatusChange::Ovp(PortState::On),
+            ]
+        );
+        let changes = changes.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(changes, ["CH1 CV->CC", "OVP ON"]);
+    }
+
+    #[test]
+    fn idn_chunked() {
+        let mut pwr = supply("KORAD KA3005P V5.8 SN:03379314");
+        assert_eq!(pwr.idn_q().unwrap(), "KORAD KA3005P V5.8 SN:03379314");
+        assert_eq!(pwr.port.sent, b"*IDN?");
+        // nothing left over for the next query
+        assert_eq!(pwr.port.pos, pwr.port.reply.len());
+    }
+
+    #[test]
+    fn idn_too_long() {
+        let long = "X".repeat(IDN_LEN_MAX + 10);
+        let mut pwr = supply(&long);
+        assert_eq!(pwr.idn_q().unwrap().len(), IDN_LEN_MAX);
+    }
+}
+
+// EOF
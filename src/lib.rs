@@ -3,14 +3,34 @@
 pub use clap::{Args, Command, Parser};
 pub use tracing::*;
 
+pub use autorange::*;
+pub use dp800::*;
+pub use instrument::*;
+pub use ka3005p::*;
+pub use load_profile::*;
 pub use scpi::*;
+pub use sdg::*;
 pub use sdl1000x::*;
+pub use sdm3000::*;
+pub use sds::*;
 pub use spd3303x::*;
 pub use startup::*;
+pub use watchdog::*;
+pub use waveform::*;
 
 pub mod startup;
 pub mod sdl1000x;
 pub mod spd3303x;
 pub mod scpi;
+pub mod load_profile;
+pub mod autorange;
+pub mod instrument;
+pub mod dp800;
+pub mod ka3005p;
+pub mod sdm3000;
+pub mod sdg;
+pub mod sds;
+pub mod watchdog;
+pub mod waveform;
 
 // EOF
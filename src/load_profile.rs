@@ -0,0 +1,330 @@
+// load_profile.rs
+#![allow(dead_code)]
+
+use std::fmt;
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// The complete static setup of the electronic load in one place,
+// so that it can be checked before anything is sent and compared afterwards.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VonConfig {
+    pub von: f32,
+    pub voff: f32,
+    pub latch: PortState,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadProfile {
+    pub func: TranFunc,
+    pub irange: IRange,
+    pub vrange: VRange,
+    // CR mode only
+    pub rrange: Option<RRange>,
+    // A, V, W or ohms depending on func
+    pub level: f32,
+    // A/µs, CC mode only, None leaves the slew alone
+    pub slew_p: Option<f32>,
+    pub slew_n: Option<f32>,
+    pub sense: PortState,
+    // None leaves Von/Voff alone
+    pub von: Option<VonConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileChange {
+    pub field: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+impl fmt::Display for ProfileChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+// the instrument rounds the values, don't report that as a change
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() <= 1e-6 + 1e-4 * a.abs().max(b.abs())
+}
+
+fn opt_str<T: fmt::Display>(v: Option<T>) -> String {
+    match v {
+        Some(v) => v.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+impl LoadProfile {
+    pub fn validate(&self, model: SDL1000XModel) -> anyhow::Result<()> {
+        let volt_max = self.vrange as u32 as f32;
+        model.func_level_check(self.func, self.irange, self.vrange, self.rrange, self.level)?;
+        if self.func != TranFunc::Res && self.rrange.is_some() {
+            return Err(anyhow!("Resistance range is only used in CR mode"));
+        }
+
+        for slew in [self.slew_p, self.slew_n].into_iter().flatten() {
+            if self.func != TranFunc::Curr {
+                return Err(anyhow!("Slew is only used in CC mode"));
+            }
+            SDL1000X::slew_check(Slew::APerUs(slew))?;
+        }
+
+        if let Some(v) = self.von {
+            level_check("Von", v.von, volt_max)?;
+            level_check("Voff", v.voff, volt_max)?;
+            if v.voff > v.von {
+                return Err(anyhow!("Voff {} is above Von {}", v.voff, v.von));
+            }
+        }
+        Ok(())
+    }
+
+    // what would change going from self to new
+    pub fn diff(&self, new: &Self) -> Vec<ProfileChange> {
+        let mut changes = Vec::new();
+        let mut push = |field, old: String, new: String| {
+            changes.push(ProfileChange { field, old, new });
+        };
+
+        if self.func != new.func {
+            push("func", self.func.to_string(), new.func.to_string());
+        }
+        if self.irange != new.irange {
+            push("irange", self.irange.to_string(), new.irange.to_string());
+        }
+        if self.vrange != new.vrange {
+            push("vrange", self.vrange.to_string(), new.vrange.to_string());
+        }
+        if self.rrange != new.rrange {
+            push("rrange", opt_str(self.rrange), opt_str(new.rrange));
+        }
+        if !close(self.level, new.level) {
+            push("level", self.level.to_string(), new.level.to_string());
+        }
+        for (field, old, new) in [
+            ("slew_p", self.slew_p, new.slew_p),
+            ("slew_n", self.slew_n, new.slew_n),
+        ] {
+            let same = match (old, new) {
+                (Some(a), Some(b)) => close(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            };
+            if !same {
+                push(field, opt_str(old), opt_str(new));
+            }
+        }
+        if self.sense != new.sense {
+            push("sense", self.sense.to_string(), new.sense.to_string());
+        }
+        match (self.von, new.von) {
+            (Some(a), Some(b)) => {
+                if !close(a.von, b.von) {
+                    push("von", a.von.to_string(), b.von.to_string());
+                }
+                if !close(a.voff, b.voff) {
+                    push("voff", a.voff.to_string(), b.voff.to_string());
+                }
+                if a.latch != b.latch {
+                    push("von_latch", a.latch.to_string(), b.latch.to_string());
+                }
+            }
+            (None, None) => {}
+            (a, b) => {
+                push("von", opt_str(a.map(|v| v.von)), opt_str(b.map(|v| v.von)));
+            }
+        }
+        changes
+    }
+}
+
+impl SDL1000X {
+    // Validate the whole profile first, then apply it with the input off.
+    // The input is left off.
+    pub fn profile_apply(&mut self, p: &LoadProfile) -> anyhow::Result<()> {
+        p.validate(self.model)?;
+
+        self.input_off()?;
+        self.short_off()?;
+        let f = p.func;
+        self.func(f.into())?;
+        self.lxi.set_s(&format!(":{f}:IRANG"), &p.irange.to_string())?;
+        self.lxi.set_s(&format!(":{f}:VRANG"), &p.vrange.to_string())?;
+        if let Some(rrange) = p.rrange {
+            self.res_rrange(rrange)?;
+        }
+        self.lxi.set_f(format!(":{f}"), p.level)?;
+        if let Some(slew) = p.slew_p {
+            self.curr_slew_p(Slew::APerUs(slew))?;
+        }
+        if let Some(slew) = p.slew_n {
+            self.curr_slew_n(Slew::APerUs(slew))?;
+        }
+        self.sense(p.sense)?;
+        if let Some(v) = p.von {
+            self.von_setup(v.von, v.voff, v.latch)?;
+        }
+        Ok(())
+    }
+
+    // read back the active setup, all optional parts that apply are filled in
+    pub fn profile_q(&mut self) -> anyhow::Result<LoadProfile> {
+        let f = TranFunc::try_from(self.func_q()?)?;
+        let ranges = self.ranges_q(f)?;
+        let level = self.lxi.get_f(format!(":{f}?"))?;
+        let (slew_p, slew_n) = match f {
+            TranFunc::Curr => (Some(self.curr_slew_p_q()?), Some(self.curr_slew_n_q()?)),
+            _ => (None, None),
+        };
+        Ok(LoadProfile {
+            func: f,
+            irange: ranges.irange,
+            vrange: ranges.vrange,
+            rrange: ranges.rrange,
+            level,
+            slew_p,
+            slew_n,
+            sense: self.sense_q()?,
+            von: Some(VonConfig {
+                von: self.von_q()?,
+                voff: self.voff_q()?,
+                latch: self.von_latch_q()?,
+            }),
+        })
+    }
+
+    // apply and read back, anything that did not stick is an error
+    pub fn profile_apply_verify(&mut self, p: &LoadProfile) -> anyhow::Result<()> {
+        self.profile_apply(p)?;
+        let mut actual = self.profile_q()?;
+        // the parts left unset in p are not compared
+        if p.slew_p.is_none() {
+            actual.slew_p = None;
+        }
+        if p.slew_n.is_none() {
+            actual.slew_n = None;
+        }
+        if p.von.is_none() {
+            actual.von = None;
+        }
+        let changes = actual.diff(p);
+        if changes.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Profile did not apply: {}",
+                changes
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        let model = SDL1000XModel::SDL1020X;
+        let cc = LoadProfile {
+            func: TranFunc::Curr,
+            irange: IRange::I5A,
+            vrange: VRange::V36V,
+            rrange: None,
+            level: 1.0,
+            slew_p: Some(0.1),
+            slew_n: None,
+            sense: PortState::Off,
+            von: None,
+        };
+        assert!(cc.validate(model).is_ok());
+
+        let p = LoadProfile {
+            level: 5.1,
+            ..cc
+        };
+        assert!(p.validate(model).is_err());
+        let p = LoadProfile {
+            level: f32::NAN,
+            ..cc
+        };
+        assert!(p.validate(model).is_err());
+        // slew is CC only
+        let p = LoadProfile {
+            func: TranFunc::Volt,
+            ..cc
+        };
+        assert!(p.validate(model).is_err());
+        let p = LoadProfile {
+            func: TranFunc::Res,
+            level: 50.0,
+            slew_p: None,
+            ..cc
+        };
+        assert!(p.validate(model).is_err());
+        let p = LoadProfile {
+            rrange: Some(RRange::Middle),
+            ..p
+        };
+        assert!(p.validate(model).is_ok());
+        let p = LoadProfile {
+            rrange: Some(RRange::Low),
+            ..p
+        };
+        assert!(p.validate(model).is_err());
+        let p = LoadProfile {
+            von: Some(VonConfig {
+                von: 1.0,
+                voff: 2.0,
+                latch: PortState::Off,
+            }),
+            ..cc
+        };
+        assert!(p.validate(model).is_err());
+    }
+
+    #[test]
+    fn diff() {
+        let old = LoadProfile {
+            func: TranFunc::Curr,
+            irange: IRange::I5A,
+            vrange: VRange::V36V,
+            rrange: None,
+            level: 1.0,
+            slew_p: Some(0.1),
+            slew_n: None,
+            sense: PortState::Off,
+            von: None,
+        };
+        assert!(old.diff(&old).is_empty());
+        // rounding by the instrument is not a change
+        let new = LoadProfile {
+            level: 1.00001,
+            ..old
+        };
+        assert!(old.diff(&new).is_empty());
+
+        let new = LoadProfile {
+            level: 2.0,
+            slew_p: None,
+            sense: PortState::On,
+            ..old
+        };
+        let changes = old
+            .diff(&new)
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(changes, ["level: 1 -> 2", "slew_p: 0.1 -> -", "sense: OFF -> ON"]);
+    }
+}
+
+// EOF
@@ -3,6 +3,8 @@
 #![allow(dead_code)]
 
 use std::{fmt, fmt::Display, time};
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::TcpStream;
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::str::FromStr;
 
@@ -12,7 +14,51 @@ use num::traits::Float;
 
 use crate::*;
 
-#[derive(Debug)]
+pub fn level_check(what: &str, val: f32, max: f32) -> anyhow::Result<()> {
+    if !val.is_finite() {
+        Err(anyhow!("{what} {val} is not a valid number."))
+    } else if val < 0.0 {
+        Err(anyhow!("{what} {val} is negative."))
+    } else if val > max {
+        Err(anyhow!("{what} {val} too high, max={max}"))
+    } else {
+        Ok(())
+    }
+}
+
+// *IDN? response is "maker,model,serial,firmware", e.g.
+// "Siglent Technologies,SPD3303X-E,SPD3XIDD4R5542,1.01.01.02.05,V3.0"
+pub fn idn_model<M>(idn: &str) -> anyhow::Result<M>
+    where
+        M: FromStr<Err = anyhow::Error>,
+{
+    match idn.split(',').nth(1) {
+        Some(m) => M::from_str(m.trim()),
+        None => Err(anyhow!("Invalid IDN format: {idn}")),
+    }
+}
+
+// unknown models are treated as the fallback model
+pub fn idn_model_or<M>(idn: &str, fallback: M) -> M
+    where
+        M: FromStr<Err = anyhow::Error> + Display,
+{
+    idn_model(idn).unwrap_or_else(|e| {
+        warn!("{e}, assuming {fallback}");
+        fallback
+    })
+}
+
+// for encoding status registers
+pub fn bit(on: bool, n: u16) -> u16 {
+    if on {
+        1 << n
+    } else {
+        0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PortState {
     On,
     Off,
@@ -28,7 +74,7 @@ impl fmt::Display for PortState {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Ch {
     Ch1,
     Ch2,
@@ -48,15 +94,13 @@ impl fmt::Display for Ch {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Meas {
     Volt,
     Curr,
     Powr,
     Res,
     Ext,
-    // don't ask
-    Dummy, // also don't ask
 }
 
 impl fmt::Display for Meas {
@@ -67,7 +111,6 @@ impl fmt::Display for Meas {
             Self::Powr => "POWER",
             Self::Res => "RES",
             Self::Ext => "EXT",
-            Self::Dummy => "###DUMMY###",
         };
         f.write_str(p)
     }
@@ -81,6 +124,8 @@ pub struct StdLxi {
 }
 
 impl LxiCommands for StdLxi {
+    type Hook = LxiTextHook;
+
     fn create<S>(name: S, addr: SocketAddr, lxi_dev: LxiTextDevice) -> Self
         where
             S: AsRef<str>,
@@ -109,15 +154,124 @@ impl LxiCommands for StdLxi {
     }
 }
 
+// Text lines as with LxiTextHook, but an IEEE 488.2 definite length block
+// ("#9000001000" + 1000 bytes) is read by its length since the data may
+// contain newlines. A block starts the response or follows the command header
+// ("C1:WF DAT2,#9..."), the header is dropped. The "\n" or "\r\n" after the
+// block is consumed with it, or skipped when the next response is read if it
+// has not arrived yet.
+pub struct ScpiBlockHook {}
+
+impl ScpiBlockHook {
+    // '#' and a digit, with nothing or only the command header before it.
+    // Not in quoted text, e.g. an error message.
+    fn block_start(head: &[u8], stream: &mut BufReader<TcpStream>) -> io::Result<bool> {
+        let after_hdr = head.ends_with(b",") && !head.contains(&b'"');
+        if !(head.is_empty() || after_hdr) {
+            return Ok(false);
+        }
+        Ok(stream.fill_buf()?.first().is_some_and(u8::is_ascii_digit))
+    }
+}
+
+impl LxiHook for ScpiBlockHook {
+    type Output = Vec<u8>;
+    fn read(stream: &mut BufReader<TcpStream>) -> io::Result<Self::Output> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut buf = Vec::new();
+        loop {
+            let mut b = [0u8; 1];
+            stream.read_exact(&mut b)?;
+            match b[0] {
+                b'\r' | b'\n' if buf.is_empty() => {}
+                b'\n' => {
+                    if buf.last() == Some(&b'\r') {
+                        buf.pop();
+                    }
+                    return Ok(buf);
+                }
+                b'#' if Self::block_start(&buf, stream)? => break,
+                c => buf.push(c),
+            }
+        }
+
+        let mut n = [0u8; 1];
+        stream.read_exact(&mut n)?;
+        let n = match n[0] {
+            c @ b'1'..=b'9' => (c - b'0') as usize,
+            b'0' => return Err(invalid("Indefinite length block (#0) not supported")),
+            _ => return Err(invalid("Invalid block length header")),
+        };
+        let mut len = vec![0u8; n];
+        stream.read_exact(&mut len)?;
+        let len = String::from_utf8_lossy(&len)
+            .parse::<usize>()
+            .map_err(|_| invalid("Invalid block length"))?;
+        let mut data = vec![0u8; len];
+        stream.read_exact(&mut data)?;
+        // only what is already buffered, the instrument may not send a terminator
+        let term = match stream.buffer() {
+            [b'\r', b'\n', ..] => 2,
+            [b'\n', ..] => 1,
+            _ => 0,
+        };
+        stream.consume(term);
+        Ok(data)
+    }
+}
+
+pub type LxiBlockDevice = LxiDevice<ScpiBlockHook>;
+
+// for instruments that send binary data, e.g. oscilloscopes
+pub struct BlockLxi {
+    pub name: String,
+    pub addr: SocketAddr,
+    pub v: bool,
+    pub lxi_dev: LxiBlockDevice,
+}
+
+impl LxiCommands for BlockLxi {
+    type Hook = ScpiBlockHook;
+
+    fn create<S>(name: S, addr: SocketAddr, lxi_dev: LxiBlockDevice) -> Self
+        where
+            S: AsRef<str>,
+    {
+        BlockLxi {
+            name: name.as_ref().to_owned(),
+            addr,
+            v: false,
+            lxi_dev,
+        }
+    }
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    fn get_v(&self) -> bool {
+        self.v
+    }
+    fn set_v(&mut self, v: bool) {
+        self.v = v;
+    }
+    fn dev(&mut self) -> &mut LxiBlockDevice {
+        &mut self.lxi_dev
+    }
+}
+
 pub trait LxiCommands {
-    fn create<S>(name: S, addr: SocketAddr, lxi_dev: LxiTextDevice) -> Self
+    type Hook: LxiHook<Output = Vec<u8>>;
+
+    fn create<S>(name: S, addr: SocketAddr, lxi_dev: LxiDevice<Self::Hook>) -> Self
         where
             S: AsRef<str>;
     fn name(&self) -> &str;
     fn addr(&self) -> SocketAddr;
     fn get_v(&self) -> bool;
     fn set_v(&mut self, v: bool);
-    fn dev(&mut self) -> &mut LxiTextDevice;
+    fn dev(&mut self) -> &mut LxiDevice<Self::Hook>;
 
     fn new<S, H>(name: S, host: H) -> anyhow::Result<Self>
         where
@@ -129,7 +283,7 @@ pub trait LxiCommands {
             None => return Err(anyhow!("Invalid address: {host}")),
             Some(a) => a,
         };
-        let mut lxi_dev = LxiTextDevice::new(
+        let mut lxi_dev = LxiDevice::<Self::Hook>::new(
             (addr.ip().to_string(), addr.port()),
             Some(time::Duration::new(5, 0)),
         );
@@ -167,12 +321,37 @@ pub trait LxiCommands {
         self.q_send(s.as_ref())
     }
 
+    // binary payloads, e.g. waveform data, only the length is logged
+    fn send_raw(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        if self.v() {
+            info!("Send: {name} <-- {n} bytes", name = self.name(), n = data.len());
+        }
+        Ok(self.dev().send(data)?)
+    }
+
     fn q_recv(&mut self) -> anyhow::Result<String> {
         let byt = self.dev().receive()?;
         let str = String::from_utf8_lossy(&byt);
         Ok(str.into_owned())
     }
 
+    // the response as bytes, with BlockLxi the payload of a binary block
+    fn recv_raw(&mut self) -> anyhow::Result<Vec<u8>> {
+        let byt = self.dev().receive()?;
+        if self.v() {
+            info!("Recv: {name} --> {n} bytes", name = self.name(), n = byt.len());
+        }
+        Ok(byt)
+    }
+
+    fn req_raw<S>(&mut self, s: S) -> anyhow::Result<Vec<u8>>
+        where
+            S: AsRef<str> + Display,
+    {
+        self.send(s.as_ref())?;
+        self.recv_raw()
+    }
+
     fn recv(&mut self) -> anyhow::Result<String> {
         let s = self.q_recv()?;
         if self.v() {
@@ -198,7 +377,7 @@ pub trait LxiCommands {
             S: AsRef<str> + Display,
             F: Float + Display,
     {
-        self.send(&format!("{} {v}", subsys.as_ref()))?;
+        self.send(format!("{} {v}", subsys.as_ref()))?;
         Ok(v)
     }
 
@@ -206,7 +385,7 @@ pub trait LxiCommands {
         where
             S: AsRef<str> + Display,
     {
-        self.send(&format!("{} {}", subsys.as_ref(), v.as_ref()))?;
+        self.send(format!("{} {}", subsys.as_ref(), v.as_ref()))?;
         Ok(v)
     }
 
@@ -227,7 +406,7 @@ pub trait LxiCommands {
         where
             S: AsRef<str> + Display,
     {
-        self.send(&format!("{} {state}", subsys.as_ref()))?;
+        self.send(format!("{} {state}", subsys.as_ref()))?;
         Ok(state)
     }
 
@@ -250,4 +429,73 @@ pub trait LxiCommands {
         Ok(matches!(resp, PortState::On))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_check_limits() {
+        assert!(level_check("Voltage", 0.0, 30.0).is_ok());
+        assert!(level_check("Voltage", 30.0, 30.0).is_ok());
+        assert!(level_check("Voltage", 30.01, 30.0).is_err());
+        assert!(level_check("Voltage", -0.1, 30.0).is_err());
+        assert!(level_check("Voltage", f32::NAN, 30.0).is_err());
+        assert!(level_check("Voltage", f32::INFINITY, 30.0).is_err());
+    }
+
+    // a scope on localhost that answers each query with a canned reply
+    fn block_server(script: &'static [(&'static str, &'static [u8])]) -> String {
+        use std::{
+            io::{BufRead, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut out = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            for (cmd, reply) in script {
+                assert_eq!(lines.next().unwrap().unwrap().trim(), *cmd);
+                out.write_all(reply).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn block_hook() {
+        let addr = block_server(&[
+            // header before the block, a newline in the data, two terminators
+            ("C1:WF? DAT2", b"C1:WF DAT2,#9000000005ab\ncd\n\n"),
+            ("C1:VDIV?", b"C1:VDIV 5.00E-01V\r\n"),
+            ("PRIN? BMP", b"#14BM\r\n\n"),
+            ("*IDN?", b"Siglent Technologies,SDS1104X-E,SDS1EDX,8.2.6.1.37R9\n"),
+            // CRLF after the block
+            ("C2:WF? DAT2", b"C2:WF DAT2,#13\x01\r\n\r\n"),
+            ("C2:VDIV?", b"C2:VDIV 2.00E+00V\r\n"),
+            // '#' in text
+            ("SYST:ERR?", b"-113,\"Undefined header; #1\"\r\n"),
+            ("FILE?", b"PNG #2\n"),
+            ("PRIN? PNG", b"#0\x89PNG\n"),
+        ]);
+        let mut lxi = BlockLxi::new("SDS", addr).unwrap();
+        assert_eq!(lxi.req_raw("C1:WF? DAT2").unwrap(), b"ab\ncd");
+        assert_eq!(lxi.req("C1:VDIV?").unwrap(), "C1:VDIV 5.00E-01V");
+        assert_eq!(lxi.req_raw("PRIN? BMP").unwrap(), b"BM\r\n");
+        assert_eq!(
+            lxi.req("*IDN?").unwrap(),
+            "Siglent Technologies,SDS1104X-E,SDS1EDX,8.2.6.1.37R9"
+        );
+        assert_eq!(lxi.req_raw("C2:WF? DAT2").unwrap(), b"\x01\r\n");
+        assert_eq!(lxi.req("C2:VDIV?").unwrap(), "C2:VDIV 2.00E+00V");
+        assert_eq!(lxi.req("SYST:ERR?").unwrap(), "-113,\"Undefined header; #1\"");
+        assert_eq!(lxi.req("FILE?").unwrap(), "PNG #2");
+        let e = lxi.req_raw("PRIN? PNG").unwrap_err();
+        assert!(e.to_string().contains("#0"), "{e}");
+    }
+}
 // EOF
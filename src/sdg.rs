@@ -0,0 +1,468 @@
+// sdg.rs
+#![allow(dead_code)]
+
+use std::{fmt, fmt::Display, net::ToSocketAddrs, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// https://int.siglent.com/upload_file/user/SDG1000X/SDG_Programming_Guide_PG02-E05C.pdf
+
+// into high impedance, half of it into 50 ohms
+const VOLT_MAX: f32 = 10.0;
+const ARB_POINTS_MIN: usize = 2;
+const ARB_SAMPLE_MAX: f32 = 32767.0;
+const BURST_CYCLES_MAX: u32 = 1_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdgCh {
+    Ch1,
+    Ch2,
+}
+
+impl From<SdgCh> for Ch {
+    fn from(c: SdgCh) -> Self {
+        match c {
+            SdgCh::Ch1 => Ch::Ch1,
+            SdgCh::Ch2 => Ch::Ch2,
+        }
+    }
+}
+
+impl Display for SdgCh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Ch::from(*self).fmt(f)
+    }
+}
+
+impl SdgCh {
+    // command header, C1 or C2
+    fn hdr(&self) -> &'static str {
+        match *self {
+            Self::Ch1 => "C1",
+            Self::Ch2 => "C2",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdgWave {
+    Sine,
+    Square,
+    Ramp,
+    Pulse,
+    Noise,
+    Arb,
+    Dc,
+}
+
+impl Display for SdgWave {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Sine => "SINE",
+            Self::Square => "SQUARE",
+            Self::Ramp => "RAMP",
+            Self::Pulse => "PULSE",
+            Self::Noise => "NOISE",
+            Self::Arb => "ARB",
+            Self::Dc => "DC",
+        })
+    }
+}
+
+impl FromStr for SdgWave {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SINE" => Ok(Self::Sine),
+            "SQUARE" => Ok(Self::Square),
+            "RAMP" => Ok(Self::Ramp),
+            "PULSE" => Ok(Self::Pulse),
+            "NOISE" => Ok(Self::Noise),
+            "ARB" => Ok(Self::Arb),
+            "DC" => Ok(Self::Dc),
+            x => Err(anyhow!("Unknown waveform {x}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdgLoad {
+    HighZ,
+    Ohm50,
+}
+
+impl Display for SdgLoad {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::HighZ => "HZ",
+            Self::Ohm50 => "50",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdgTrigSource {
+    Internal,
+    External,
+    Manual,
+}
+
+impl Display for SdgTrigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Internal => "INT",
+            Self::External => "EXT",
+            Self::Manual => "MAN",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SDGModel {
+    SDG1032X,
+    SDG1062X,
+    SDG2042X,
+    SDG2082X,
+    SDG2122X,
+}
+
+impl SDGModel {
+    // sine, in Hz
+    pub fn freq_max(&self) -> f32 {
+        match *self {
+            Self::SDG1032X => 30e6,
+            Self::SDG1062X => 60e6,
+            Self::SDG2042X => 40e6,
+            Self::SDG2082X => 80e6,
+            Self::SDG2122X => 120e6,
+        }
+    }
+    pub fn arb_points_max(&self) -> usize {
+        match *self {
+            Self::SDG1032X | Self::SDG1062X => 16 * 1024,
+            Self::SDG2042X | Self::SDG2082X | Self::SDG2122X => 8 * 1024 * 1024,
+        }
+    }
+}
+
+impl Display for SDGModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::SDG1032X => "SDG1032X",
+            Self::SDG1062X => "SDG1062X",
+            Self::SDG2042X => "SDG2042X",
+            Self::SDG2082X => "SDG2082X",
+            Self::SDG2122X => "SDG2122X",
+        })
+    }
+}
+
+impl FromStr for SDGModel {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SDG1032X" => Ok(Self::SDG1032X),
+            "SDG1062X" => Ok(Self::SDG1062X),
+            "SDG2042X" => Ok(Self::SDG2042X),
+            "SDG2082X" => Ok(Self::SDG2082X),
+            "SDG2122X" => Ok(Self::SDG2122X),
+            x => Err(anyhow!("Unknown model {x}")),
+        }
+    }
+}
+
+// the parts of BSWV? that are common to all waveforms
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SdgBasicWave {
+    pub wave: SdgWave,
+    // Hz
+    pub freq: f32,
+    // Vpp
+    pub amp: f32,
+    // V
+    pub offset: f32,
+}
+
+impl Display for SdgBasicWave {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}Hz {}Vpp offset {}V",
+            self.wave, self.freq, self.amp, self.offset
+        )
+    }
+}
+
+// "1000HZ", "2V" -> the number, the generator appends units to the values
+fn strip_unit(v: &str) -> anyhow::Result<f32> {
+    let n = v.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    n.parse::<f32>()
+        .map_err(|e| anyhow!("Cannot parse {v}: {e}"))
+}
+
+impl FromStr for SdgBasicWave {
+    type Err = anyhow::Error;
+    // "C1:BSWV WVTP,SINE,FRQ,100HZ,PERI,0.01S,AMP,2V,AMPVRMS,0.707Vrms,OFST,0V,..."
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let params = s
+            .split_once(' ')
+            .map(|(_, p)| p)
+            .ok_or(anyhow!("Invalid BSWV format: {s}"))?;
+        let fields = params.trim().split(',').collect::<Vec<&str>>();
+        let get = |key: &str| {
+            fields
+                .chunks(2)
+                .find(|kv| kv[0] == key && kv.len() == 2)
+                .map(|kv| kv[1])
+        };
+        let wave = SdgWave::from_str(get("WVTP").ok_or(anyhow!("No WVTP in {s}"))?)?;
+        // DC and noise do not have all of these
+        let num = |key: &str| get(key).map(strip_unit).unwrap_or(Ok(0.0));
+        Ok(Self {
+            wave,
+            freq: num("FRQ")?,
+            amp: num("AMP")?,
+            offset: num("OFST")?,
+        })
+    }
+}
+
+pub struct SDG {
+    pub lxi: StdLxi,
+    pub model: SDGModel,
+}
+
+impl SDG {
+    pub fn new<S, H>(name: S, host: H) -> anyhow::Result<Self>
+        where
+            S: AsRef<str>,
+            H: fmt::Display + AsRef<str> + ToSocketAddrs,
+            Self: Sized,
+    {
+        let mut gen = Self {
+            lxi: StdLxi::new(name, host)?,
+            model: SDGModel::SDG1032X,
+        };
+        gen.detect_model()?;
+        Ok(gen)
+    }
+
+    pub fn detect_model(&mut self) -> anyhow::Result<SDGModel> {
+        let idn = self.idn_q()?;
+        self.model = idn_model_or(&idn, self.model);
+        Ok(self.model)
+    }
+
+    pub fn idn_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req("*IDN?")
+    }
+    pub fn error_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req("SYST:ERR?")
+    }
+
+    fn bswv<S: AsRef<str> + Display>(&mut self, c: SdgCh, param: S) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:BSWV {param}", c.hdr()))
+    }
+    pub fn bswv_q(&mut self, c: SdgCh) -> anyhow::Result<SdgBasicWave> {
+        SdgBasicWave::from_str(&self.lxi.req(format!("{}:BSWV?", c.hdr()))?)
+    }
+
+    pub fn wave(&mut self, c: SdgCh, w: SdgWave) -> anyhow::Result<SdgWave> {
+        self.bswv(c, format!("WVTP,{w}"))?;
+        Ok(w)
+    }
+    pub fn freq(&mut self, c: SdgCh, freq: f32) -> anyhow::Result<f32> {
+        let max = self.model.freq_max();
+        if !(freq > 0.0 && freq <= max) {
+            return Err(anyhow!("Frequency {freq} invalid, max={max}"));
+        }
+        self.bswv(c, format!("FRQ,{freq}"))?;
+        Ok(freq)
+    }
+    pub fn amp(&mut self, c: SdgCh, amp: f32) -> anyhow::Result<f32> {
+        level_check("Amplitude", amp, 2.0 * VOLT_MAX)?;
+        self.bswv(c, format!("AMP,{amp}"))?;
+        Ok(amp)
+    }
+    pub fn offset(&mut self, c: SdgCh, offset: f32) -> anyhow::Result<f32> {
+        level_check("Offset", offset.abs(), VOLT_MAX)?;
+        self.bswv(c, format!("OFST,{offset}"))?;
+        Ok(offset)
+    }
+    // square and pulse only, in %
+    pub fn duty(&mut self, c: SdgCh, duty: f32) -> anyhow::Result<f32> {
+        level_check("Duty cycle", duty, 100.0)?;
+        self.bswv(c, format!("DUTY,{duty}"))?;
+        Ok(duty)
+    }
+
+    // everything in one command so the output does not glitch through the
+    // intermediate settings
+    pub fn setup(&mut self, c: SdgCh, w: &SdgBasicWave) -> anyhow::Result<()> {
+        let max = self.model.freq_max();
+        if w.wave != SdgWave::Dc && !(w.freq > 0.0 && w.freq <= max) {
+            return Err(anyhow!("Frequency {} invalid, max={max}", w.freq));
+        }
+        level_check("Amplitude", w.amp, 2.0 * VOLT_MAX)?;
+        level_check("Peak voltage", w.offset.abs() + w.amp / 2.0, VOLT_MAX)?;
+        self.bswv(
+            c,
+            format!(
+                "WVTP,{},FRQ,{},AMP,{},OFST,{}",
+                w.wave, w.freq, w.amp, w.offset
+            ),
+        )
+    }
+
+    pub fn output_state(&mut self, c: SdgCh, state: PortState) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:OUTP {state}", c.hdr()))
+    }
+    pub fn output_on(&mut self, c: SdgCh) -> anyhow::Result<()> {
+        self.output_state(c, PortState::On)
+    }
+    pub fn output_off(&mut self, c: SdgCh) -> anyhow::Result<()> {
+        self.output_state(c, PortState::Off)
+    }
+    // "C1:OUTP ON,LOAD,HZ,PLRT,NOR"
+    pub fn output_q(&mut self, c: SdgCh) -> anyhow::Result<PortState> {
+        let resp = self.lxi.req(format!("{}:OUTP?", c.hdr()))?;
+        Ok(match resp.split([' ', ',']).nth(1) {
+            Some("ON") => PortState::On,
+            _ => PortState::Off,
+        })
+    }
+    // the displayed amplitude assumes this load, the output impedance is always 50 ohms
+    pub fn output_load(&mut self, c: SdgCh, load: SdgLoad) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:OUTP LOAD,{load}", c.hdr()))
+    }
+
+    // Burst mode: a number of cycles per trigger instead of a continuous output
+    pub fn burst(&mut self, c: SdgCh, state: PortState) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:BTWV STATE,{state}", c.hdr()))
+    }
+    pub fn burst_cycles(&mut self, c: SdgCh, cycles: u32) -> anyhow::Result<u32> {
+        if cycles == 0 || cycles > BURST_CYCLES_MAX {
+            return Err(anyhow!("Invalid burst cycles {cycles}, must be 1..{BURST_CYCLES_MAX}"));
+        }
+        self.lxi.send(format!("{}:BTWV GATE_NCYC,NCYC,TIME,{cycles}", c.hdr()))?;
+        Ok(cycles)
+    }
+    pub fn burst_source(&mut self, c: SdgCh, src: SdgTrigSource) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:BTWV TRSR,{src}", c.hdr()))
+    }
+    // burst repetition period with the internal trigger, in seconds
+    pub fn burst_period(&mut self, c: SdgCh, period: f32) -> anyhow::Result<f32> {
+        if period <= 0.0 || !period.is_finite() {
+            return Err(anyhow!("Invalid burst period {period}"));
+        }
+        self.lxi.send(format!("{}:BTWV PRD,{period}", c.hdr()))?;
+        Ok(period)
+    }
+    pub fn burst_delay(&mut self, c: SdgCh, delay: f32) -> anyhow::Result<f32> {
+        level_check("Burst delay", delay, 100.0)?;
+        self.lxi.send(format!("{}:BTWV DLAY,{delay}", c.hdr()))?;
+        Ok(delay)
+    }
+    // manual trigger, needs SdgTrigSource::Manual
+    pub fn burst_trg(&mut self, c: SdgCh) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:BTWV MTRIG", c.hdr()))
+    }
+
+    // Upload the samples (in volts, interval seconds apart) as a user arb waveform
+    // and select it. The samples are scaled to the full DAC range and the
+    // amplitude and offset are set to reproduce the original levels.
+    pub fn arb_upload(
+        &mut self,
+        c: SdgCh,
+        name: &str,
+        points: &[f32],
+        interval: f32,
+    ) -> anyhow::Result<()> {
+        let max_points = self.model.arb_points_max();
+        if points.len() < ARB_POINTS_MIN || points.len() > max_points {
+            return Err(anyhow!(
+                "Invalid arb length {}, must be {ARB_POINTS_MIN}..{max_points}",
+                points.len()
+            ));
+        }
+        if !(interval > 0.0 && interval.is_finite()) {
+            return Err(anyhow!("Invalid sample interval {interval}"));
+        }
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(anyhow!("Invalid arb name {name:?}"));
+        }
+        let (min, max) = points
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), v| (min.min(*v), max.max(*v)));
+        if !(min.is_finite() && max.is_finite()) {
+            return Err(anyhow!("Arb waveform has invalid points"));
+        }
+        let amp = max - min;
+        let offset = (max + min) / 2.0;
+        level_check("Peak voltage", offset.abs() + amp / 2.0, VOLT_MAX)?;
+        let freq = 1.0 / (interval * points.len() as f32);
+
+        let mut data = format!(
+            "{}:WVDT WVNM,{name},FREQ,{freq},AMPL,{amp},OFST,{offset},PHASE,0,WAVEDATA,",
+            c.hdr()
+        )
+        .into_bytes();
+        for v in points {
+            // a flat waveform is all zeros around the offset
+            let s = if amp > 0.0 {
+                (v - offset) / (amp / 2.0) * ARB_SAMPLE_MAX
+            } else {
+                0.0
+            };
+            data.extend_from_slice(&(s.round() as i16).to_le_bytes());
+        }
+        self.lxi.send_raw(&data)?;
+        self.lxi.send(format!("{}:ARWV NAME,{name}", c.hdr()))?;
+        self.bswv(c, format!("WVTP,ARB,FRQ,{freq},AMP,{amp},OFST,{offset}"))
+    }
+    pub fn arb_upload_wave(&mut self, c: SdgCh, name: &str, w: &Waveform) -> anyhow::Result<()> {
+        self.arb_upload(c, name, &w.points, w.interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_wave_from_str() {
+        let w = SdgBasicWave::from_str(
+            "C1:BSWV WVTP,SINE,FRQ,100HZ,PERI,0.01S,AMP,2V,AMPVRMS,0.707Vrms,OFST,-0.5V,HLEV,0.5V,LLEV,-1.5V,PHSE,0",
+        )
+        .unwrap();
+        assert_eq!(
+            w,
+            SdgBasicWave {
+                wave: SdgWave::Sine,
+                freq: 100.0,
+                amp: 2.0,
+                offset: -0.5,
+            }
+        );
+
+        let w = SdgBasicWave::from_str("C2:BSWV WVTP,SQUARE,FRQ,1.5e+06HZ,AMP,0.1V,OFST,0V,DUTY,50").unwrap();
+        assert_eq!(w.wave, SdgWave::Square);
+        assert_eq!(w.freq, 1.5e6);
+
+        // DC only has an offset
+        let w = SdgBasicWave::from_str("C1:BSWV WVTP,DC,OFST,3.3V\n").unwrap();
+        assert_eq!((w.wave, w.freq, w.amp, w.offset), (SdgWave::Dc, 0.0, 0.0, 3.3));
+    }
+
+    #[test]
+    fn basic_wave_from_str_errors() {
+        assert!(SdgBasicWave::from_str("WVTP,SINE").is_err());
+        assert!(SdgBasicWave::from_str("C1:BSWV FRQ,100HZ,AMP,2V").is_err());
+        assert!(SdgBasicWave::from_str("C1:BSWV WVTP,TRIANGLE").is_err());
+        assert!(SdgBasicWave::from_str("C1:BSWV WVTP,SINE,FRQ,fastHZ").is_err());
+    }
+}
+
+// EOF
@@ -1,7 +1,7 @@
 // sdl1000x.rs
 #![allow(dead_code)]
 
-use std::{fmt, fmt::Display, net::ToSocketAddrs, str::FromStr};
+use std::{fmt, fmt::Display, net::ToSocketAddrs, str::FromStr, thread, time};
 
 use anyhow::anyhow;
 
@@ -11,9 +11,149 @@ use crate::*;
 
 const SLEW_MIN: f32 = 0.001;
 const SLEW_MAX: f32 = 0.500;
+const RCO_MIN: f32 = 0.01;
+const RCO_MAX: f32 = 1.0;
+// transient A/B widths in seconds
+const TRAN_WIDTH_MIN: f32 = 0.00002;
+const TRAN_WIDTH_MAX: f32 = 999.0;
+const LIST_STEPS_MAX: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SDL1000XModel {
+    SDL1020X,
+    SDL1020XE,
+    SDL1030X,
+    SDL1030XE,
+}
+
+impl SDL1000XModel {
+    pub fn powr_max(&self) -> f32 {
+        match *self {
+            Self::SDL1020X | Self::SDL1020XE => 200.0,
+            Self::SDL1030X | Self::SDL1030XE => 300.0,
+        }
+    }
+
+    // check a level of the function against the ranges it is used with
+    pub fn func_level_check(
+        &self,
+        f: TranFunc,
+        irange: IRange,
+        vrange: VRange,
+        rrange: Option<RRange>,
+        val: f32,
+    ) -> anyhow::Result<()> {
+        let curr_max = irange as u32 as f32;
+        let volt_max = vrange as u32 as f32;
+        match f {
+            TranFunc::Curr => level_check("Current", val, curr_max),
+            TranFunc::Volt => level_check("Voltage", val, volt_max),
+            TranFunc::Powr => level_check("Power", val, (curr_max * volt_max).min(self.powr_max())),
+            TranFunc::Res => match rrange {
+                Some(rrange) => rrange.check(val),
+                None => Err(anyhow!("CR mode needs a resistance range")),
+            },
+        }
+    }
+}
+
+impl Display for SDL1000XModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::SDL1020X => "SDL1020X",
+            Self::SDL1020XE => "SDL1020X-E",
+            Self::SDL1030X => "SDL1030X",
+            Self::SDL1030XE => "SDL1030X-E",
+        })
+    }
+}
+
+impl FromStr for SDL1000XModel {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SDL1020X" => Ok(Self::SDL1020X),
+            "SDL1020X-E" => Ok(Self::SDL1020XE),
+            "SDL1030X" => Ok(Self::SDL1030X),
+            "SDL1030X-E" => Ok(Self::SDL1030XE),
+            x => Err(anyhow!("Unknown model {x}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProtConfig {
+    pub state: PortState,
+    // A or W
+    pub level: f32,
+    pub delay: time::Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SDL1000XStatus {
+    pub volt_fault: bool,
+    pub over_curr: bool,
+    pub over_powr: bool,
+    pub over_temp: bool,
+    pub reverse_volt: bool,
+}
+
+impl SDL1000XStatus {
+    pub fn from_u16(st: u16) -> Self {
+        Self {
+            volt_fault: st & 1 != 0,
+            over_curr: st & (1 << 1) != 0,
+            over_powr: st & (1 << 3) != 0,
+            over_temp: st & (1 << 4) != 0,
+            reverse_volt: st & (1 << 7) != 0,
+        }
+    }
+
+    pub fn to_u16(&self) -> u16 {
+        bit(self.volt_fault, 0)
+            | bit(self.over_curr, 1)
+            | bit(self.over_powr, 3)
+            | bit(self.over_temp, 4)
+            | bit(self.reverse_volt, 7)
+    }
+
+    pub fn tripped(&self) -> bool {
+        self.to_u16() != 0
+    }
+}
+
+impl Display for SDL1000XStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.volt_fault, "VF"),
+            (self.over_curr, "OC"),
+            (self.over_powr, "OP"),
+            (self.over_temp, "OT"),
+            (self.reverse_volt, "RV"),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| *name)
+        .collect::<Vec<&str>>();
+        if flags.is_empty() {
+            f.write_str("OK")
+        } else {
+            f.write_str(&flags.join(","))
+        }
+    }
+}
+
+impl FromStr for SDL1000XStatus {
+    type Err = anyhow::Error;
+    fn from_str(st_str: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_u16(st_str.trim().parse::<u16>()?))
+    }
+}
 
 pub struct SDL1000X {
     pub lxi: StdLxi,
+    pub model: SDL1000XModel,
+    batt: BattEnergy,
 }
 
 impl SDL1000X {
@@ -23,9 +163,19 @@ impl SDL1000X {
             H: fmt::Display + AsRef<str> + ToSocketAddrs,
             Self: Sized,
     {
-        Ok(Self {
+        let mut load = Self {
             lxi: StdLxi::new(name, host)?,
-        })
+            model: SDL1000XModel::SDL1020X,
+            batt: BattEnergy::default(),
+        };
+        load.detect_model()?;
+        Ok(load)
+    }
+
+    pub fn detect_model(&mut self) -> anyhow::Result<SDL1000XModel> {
+        let idn = self.idn_q()?;
+        self.model = idn_model_or(&idn, self.model);
+        Ok(self.model)
     }
 
     pub fn idn_q(&mut self) -> anyhow::Result<String> {
@@ -45,46 +195,62 @@ impl SDL1000X {
     }
 
     pub fn func(&mut self, func: Func) -> anyhow::Result<Func> {
-        self.lxi.send(&format!(":FUNC {func}"))?;
+        self.lxi.send(format!(":FUNC {func}"))?;
         Ok(func)
     }
     pub fn func_q(&mut self) -> anyhow::Result<Func> {
         Func::from_str(self.lxi.req(":FUNC?")?.as_str())
     }
 
-    pub fn meas_q(&mut self, m: Meas) -> anyhow::Result<f32> {
-        match m {
-            Meas::Volt | Meas::Curr | Meas::Powr | Meas::Res | Meas::Ext => {}
-            _ => {
-                return Err(anyhow!("Device cannot measure {m}"));
-            }
-        }
-
-        self.lxi.get_f(&format!("MEAS:{m}?"))
+    pub fn meas_q(&mut self, m: LoadMeas) -> anyhow::Result<f32> {
+        self.lxi.get_f(format!("MEAS:{m}?"))
     }
     pub fn volt_m(&mut self) -> anyhow::Result<f32> {
-        self.meas_q(Meas::Volt)
+        self.meas_q(LoadMeas::Volt)
     }
     pub fn curr_m(&mut self) -> anyhow::Result<f32> {
-        self.meas_q(Meas::Curr)
+        self.meas_q(LoadMeas::Curr)
     }
     pub fn powr_m(&mut self) -> anyhow::Result<f32> {
-        self.meas_q(Meas::Powr)
+        self.meas_q(LoadMeas::Powr)
     }
     pub fn res_m(&mut self) -> anyhow::Result<f32> {
-        self.meas_q(Meas::Res)
+        self.meas_q(LoadMeas::Res)
     }
     pub fn ext_m(&mut self) -> anyhow::Result<f32> {
-        self.meas_q(Meas::Ext)
+        self.meas_q(LoadMeas::Ext)
     }
 
-    // wave type can be "curr", "volt", "pow", "res"
-    pub fn wave_q(&mut self, m: Meas) -> anyhow::Result<Vec<f32>> {
-        let c = format!("MEAS:WAVE? {m}");
-        let w = self.lxi.req(&c)?;
-        Ok(w.split(',')
-            .map(|x| x.parse::<f32>().unwrap_or_default())
-            .collect::<Vec<f32>>())
+    // External control: the level follows the rear panel analog input,
+    // 0..10V maps to the full scale of the active range.
+    pub fn ext_control(&mut self, f: ExtFunc, state: PortState) -> anyhow::Result<PortState> {
+        self.lxi.set_state(format!(":{f}:EXT:INP"), state)
+    }
+    pub fn ext_control_on(&mut self, f: ExtFunc) -> anyhow::Result<PortState> {
+        self.ext_control(f, PortState::On)
+    }
+    pub fn ext_control_off(&mut self, f: ExtFunc) -> anyhow::Result<PortState> {
+        self.ext_control(f, PortState::Off)
+    }
+    pub fn ext_control_q(&mut self, f: ExtFunc) -> anyhow::Result<PortState> {
+        self.lxi.get_state(format!(":{f}:EXT:INP?"))
+    }
+
+    // time span of the waveform display in seconds, the points are spread evenly over it
+    pub fn wave_time(&mut self, secs: f32) -> anyhow::Result<f32> {
+        if !(secs > 0.0 && secs.is_finite()) {
+            return Err(anyhow!("Wave time {secs} must be positive"));
+        }
+        self.lxi.set_f(":WAVE:TIME", secs)
+    }
+    pub fn wave_time_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":WAVE:TIME?")
+    }
+    pub fn wave_q(&mut self, m: WaveMeas) -> anyhow::Result<Waveform> {
+        let span = self.wave_time_q()?;
+        let data = self.lxi.req(format!("MEAS:WAVE? {m}"))?;
+        let n = data.split(',').count() as f32;
+        Waveform::parse(m.into(), span / n, &data)
     }
 
     pub fn sense(&mut self, state: PortState) -> anyhow::Result<PortState> {
@@ -126,6 +292,90 @@ impl SDL1000X {
         self.lxi.get_state(":SHOR:STAT?")
     }
 
+    // The load does not sink before the input voltage has reached Von.
+    // With latch on it keeps sinking until the voltage drops below Voff,
+    // with latch off it stops as soon as the voltage is below Von again.
+    pub fn von(&mut self, v: f32) -> anyhow::Result<f32> {
+        level_check("Von", v, VRange::V150V as u32 as f32)?;
+        self.lxi.set_f(":VOLT:ON", v)
+    }
+    pub fn von_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":VOLT:ON?")
+    }
+    pub fn voff(&mut self, v: f32) -> anyhow::Result<f32> {
+        level_check("Voff", v, VRange::V150V as u32 as f32)?;
+        self.lxi.set_f(":VOLT:OFF", v)
+    }
+    pub fn voff_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":VOLT:OFF?")
+    }
+    pub fn von_latch(&mut self, state: PortState) -> anyhow::Result<PortState> {
+        self.lxi.set_state(":VOLT:LATC", state)
+    }
+    pub fn von_latch_q(&mut self) -> anyhow::Result<PortState> {
+        self.lxi.get_state(":VOLT:LATC?")
+    }
+    pub fn von_setup(&mut self, von: f32, voff: f32, latch: PortState) -> anyhow::Result<()> {
+        if voff > von {
+            return Err(anyhow!("Voff {voff} is above Von {von}"));
+        }
+        self.von(von)?;
+        self.voff(voff)?;
+        self.von_latch(latch)?;
+        Ok(())
+    }
+
+    pub fn curr_prot(&mut self, cfg: ProtConfig) -> anyhow::Result<ProtConfig> {
+        level_check("Current protection", cfg.level, IRange::I30A as u32 as f32)?;
+        self.prot_set("CURR", cfg)
+    }
+    pub fn curr_prot_q(&mut self) -> anyhow::Result<ProtConfig> {
+        self.prot_get("CURR")
+    }
+    pub fn powr_prot(&mut self, cfg: ProtConfig) -> anyhow::Result<ProtConfig> {
+        level_check("Power protection", cfg.level, self.model.powr_max())?;
+        self.prot_set("POW", cfg)
+    }
+    pub fn powr_prot_q(&mut self) -> anyhow::Result<ProtConfig> {
+        self.prot_get("POW")
+    }
+    fn prot_set(&mut self, subsys: &str, cfg: ProtConfig) -> anyhow::Result<ProtConfig> {
+        self.lxi.set_f(format!(":{subsys}:PROT:LEV"), cfg.level)?;
+        self.lxi.set_f(format!(":{subsys}:PROT:DEL"), cfg.delay.as_secs_f32())?;
+        self.lxi.set_state(format!(":{subsys}:PROT:STAT"), cfg.state)?;
+        Ok(cfg)
+    }
+    fn prot_get(&mut self, subsys: &str) -> anyhow::Result<ProtConfig> {
+        Ok(ProtConfig {
+            state: self.lxi.get_state(format!(":{subsys}:PROT:STAT?"))?,
+            level: self.lxi.get_f(format!(":{subsys}:PROT:LEV?"))?,
+            delay: time::Duration::try_from_secs_f32(
+                self.lxi.get_f(format!(":{subsys}:PROT:DEL?"))?,
+            )?,
+        })
+    }
+    pub fn prot_clear(&mut self) -> anyhow::Result<()> {
+        self.lxi.send(":PROT:CLE")
+    }
+
+    // questionable condition register, shows what is active right now
+    pub fn status_q(&mut self) -> anyhow::Result<SDL1000XStatus> {
+        SDL1000XStatus::from_str(self.lxi.req("STAT:QUES:COND?")?.as_str())
+    }
+    // questionable event register, latched until read
+    pub fn status_event_q(&mut self) -> anyhow::Result<SDL1000XStatus> {
+        SDL1000XStatus::from_str(self.lxi.req("STAT:QUES:EVEN?")?.as_str())
+    }
+    // error out if the load has gone into protection, measurements are garbage then
+    pub fn prot_check(&mut self) -> anyhow::Result<()> {
+        let st = self.status_q()?;
+        if st.tripped() {
+            Err(anyhow!("{name} in protection: {st}", name = self.lxi.name()))
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn curr_irange(&mut self, v: IRange) -> anyhow::Result<()> {
         self.lxi.set_s(":CURR:IRANG", &v.to_string())?;
         Ok(())
@@ -142,12 +392,7 @@ impl SDL1000X {
     }
     pub fn curr_check(&mut self, curr: Curr) -> anyhow::Result<()> {
         if let Curr::A(val) = curr {
-            let curr_max = self.curr_irange_q()? as u32 as f32;
-            if val < 0.0 {
-                return Err(anyhow!("Current {val} is negative."));
-            } else if val > curr_max {
-                return Err(anyhow!("Current {val} too high, max={curr_max}"));
-            }
+            level_check("Current", val, self.curr_irange_q()? as u32 as f32)?;
         }
         Ok(())
     }
@@ -160,6 +405,149 @@ impl SDL1000X {
         self.lxi.get_f(":CURR?")
     }
 
+    pub fn volt_irange(&mut self, v: IRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":VOLT:IRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn volt_irange_q(&mut self) -> anyhow::Result<IRange> {
+        IRange::from_str(self.lxi.req(":VOLT:IRANG?")?.as_str())
+    }
+    pub fn volt_vrange(&mut self, v: VRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":VOLT:VRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn volt_vrange_q(&mut self) -> anyhow::Result<VRange> {
+        VRange::from_str(self.lxi.req(":VOLT:VRANG?")?.as_str())
+    }
+    pub fn volt_check(&mut self, volt: Volt) -> anyhow::Result<()> {
+        if let Volt::V(val) = volt {
+            level_check("Voltage", val, self.volt_vrange_q()? as u32 as f32)?;
+        }
+        Ok(())
+    }
+    pub fn volt_volt(&mut self, v: Volt) -> anyhow::Result<()> {
+        self.volt_check(v)?;
+        self.lxi.set_s(":VOLT", &v.to_string())?;
+        Ok(())
+    }
+    pub fn volt_volt_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":VOLT?")
+    }
+
+    pub fn powr_irange(&mut self, v: IRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":POW:IRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn powr_irange_q(&mut self) -> anyhow::Result<IRange> {
+        IRange::from_str(self.lxi.req(":POW:IRANG?")?.as_str())
+    }
+    pub fn powr_vrange(&mut self, v: VRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":POW:VRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn powr_vrange_q(&mut self) -> anyhow::Result<VRange> {
+        VRange::from_str(self.lxi.req(":POW:VRANG?")?.as_str())
+    }
+    pub fn powr_check(&mut self, powr: Powr) -> anyhow::Result<()> {
+        if let Powr::W(val) = powr {
+            // the ranges may allow more than the model can dissipate
+            let curr_max = self.powr_irange_q()? as u32 as f32;
+            let volt_max = self.powr_vrange_q()? as u32 as f32;
+            level_check("Power", val, (curr_max * volt_max).min(self.model.powr_max()))?;
+        }
+        Ok(())
+    }
+    pub fn powr_powr(&mut self, p: Powr) -> anyhow::Result<()> {
+        self.powr_check(p)?;
+        self.lxi.set_s(":POW", &p.to_string())?;
+        Ok(())
+    }
+    pub fn powr_powr_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":POW?")
+    }
+
+    pub fn res_irange(&mut self, v: IRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":RES:IRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn res_irange_q(&mut self) -> anyhow::Result<IRange> {
+        IRange::from_str(self.lxi.req(":RES:IRANG?")?.as_str())
+    }
+    pub fn res_vrange(&mut self, v: VRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":RES:VRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn res_vrange_q(&mut self) -> anyhow::Result<VRange> {
+        VRange::from_str(self.lxi.req(":RES:VRANG?")?.as_str())
+    }
+    pub fn res_rrange(&mut self, v: RRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":RES:RRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn res_rrange_q(&mut self) -> anyhow::Result<RRange> {
+        RRange::from_str(self.lxi.req(":RES:RRANG?")?.as_str())
+    }
+    pub fn res_check(&mut self, res: Res) -> anyhow::Result<()> {
+        if let Res::Ohm(val) = res {
+            self.res_rrange_q()?.check(val)?;
+        }
+        Ok(())
+    }
+    pub fn res_res(&mut self, r: Res) -> anyhow::Result<()> {
+        self.res_check(r)?;
+        self.lxi.set_s(":RES", &r.to_string())?;
+        Ok(())
+    }
+    pub fn res_res_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":RES?")
+    }
+
+    // LED emulation: Vo is the forward voltage, Io the operating current
+    // and Rco the coefficient of the dynamic resistance
+    pub fn led_irange(&mut self, v: IRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":LED:IRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn led_irange_q(&mut self) -> anyhow::Result<IRange> {
+        IRange::from_str(self.lxi.req(":LED:IRANG?")?.as_str())
+    }
+    pub fn led_vrange(&mut self, v: VRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":LED:VRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn led_vrange_q(&mut self) -> anyhow::Result<VRange> {
+        VRange::from_str(self.lxi.req(":LED:VRANG?")?.as_str())
+    }
+    pub fn led_volt(&mut self, v: Volt) -> anyhow::Result<()> {
+        if let Volt::V(val) = v {
+            level_check("LED voltage", val, self.led_vrange_q()? as u32 as f32)?;
+        }
+        self.lxi.set_s(":LED:VOLT", &v.to_string())?;
+        Ok(())
+    }
+    pub fn led_volt_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":LED:VOLT?")
+    }
+    pub fn led_curr(&mut self, c: Curr) -> anyhow::Result<()> {
+        if let Curr::A(val) = c {
+            level_check("LED current", val, self.led_irange_q()? as u32 as f32)?;
+        }
+        self.lxi.set_s(":LED:CURR", &c.to_string())?;
+        Ok(())
+    }
+    pub fn led_curr_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":LED:CURR?")
+    }
+    pub fn led_rco(&mut self, rco: f32) -> anyhow::Result<f32> {
+        if !(RCO_MIN..=RCO_MAX).contains(&rco) {
+            return Err(anyhow!("Rco {rco} out of range {RCO_MIN}..{RCO_MAX}"));
+        }
+        self.lxi.set_f(":LED:RCON", rco)
+    }
+    pub fn led_rco_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":LED:RCON?")
+    }
+
     pub fn slew_check(slew: Slew) -> anyhow::Result<()> {
         if let Slew::APerUs(val) = slew {
             if val < SLEW_MIN {
@@ -186,6 +574,637 @@ impl SDL1000X {
     pub fn curr_slew_n_q(&mut self) -> anyhow::Result<f32> {
         self.lxi.get_f(":CURR:SLEW:NEG?")
     }
+
+    // Transient (dynamic) mode: the load switches between levels A and B,
+    // the levels are in A, V, W or ohms depending on the function.
+    pub fn tran_func(&mut self, f: TranFunc) -> anyhow::Result<TranFunc> {
+        self.lxi.send(format!(":FUNC:TRAN {f}"))?;
+        Ok(f)
+    }
+    pub fn tran_func_q(&mut self) -> anyhow::Result<TranFunc> {
+        TranFunc::from_str(self.lxi.req(":FUNC:TRAN?")?.as_str())
+    }
+    pub fn tran_mode(&mut self, f: TranFunc, m: Transient) -> anyhow::Result<Transient> {
+        self.lxi.send(format!(":{f}:TRAN:MODE {m}"))?;
+        Ok(m)
+    }
+    pub fn tran_mode_q(&mut self, f: TranFunc) -> anyhow::Result<Transient> {
+        Transient::from_str(self.lxi.req(format!(":{f}:TRAN:MODE?"))?.as_str())
+    }
+    pub fn tran_irange(&mut self, f: TranFunc, v: IRange) -> anyhow::Result<()> {
+        self.lxi.set_s(&format!(":{f}:TRAN:IRANG"), &v.to_string())?;
+        Ok(())
+    }
+    pub fn tran_irange_q(&mut self, f: TranFunc) -> anyhow::Result<IRange> {
+        IRange::from_str(self.lxi.req(format!(":{f}:TRAN:IRANG?"))?.as_str())
+    }
+    pub fn tran_vrange(&mut self, f: TranFunc, v: VRange) -> anyhow::Result<()> {
+        self.lxi.set_s(&format!(":{f}:TRAN:VRANG"), &v.to_string())?;
+        Ok(())
+    }
+    pub fn tran_vrange_q(&mut self, f: TranFunc) -> anyhow::Result<VRange> {
+        VRange::from_str(self.lxi.req(format!(":{f}:TRAN:VRANG?"))?.as_str())
+    }
+    pub fn tran_rrange(&mut self, v: RRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":RES:TRAN:RRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn tran_rrange_q(&mut self) -> anyhow::Result<RRange> {
+        RRange::from_str(self.lxi.req(":RES:TRAN:RRANG?")?.as_str())
+    }
+    pub fn tran_level_check(&mut self, f: TranFunc, val: f32) -> anyhow::Result<()> {
+        self.func_level_check(f, &format!(":{f}:TRAN"), val)
+    }
+    // check a level against the ranges configured in the given subsystem
+    fn func_level_check(&mut self, f: TranFunc, subsys: &str, val: f32) -> anyhow::Result<()> {
+        let irange = IRange::from_str(self.lxi.req(format!("{subsys}:IRANG?"))?.as_str())?;
+        let vrange = VRange::from_str(self.lxi.req(format!("{subsys}:VRANG?"))?.as_str())?;
+        let rrange = match f {
+            TranFunc::Res => Some(RRange::from_str(self.lxi.req(format!("{subsys}:RRANG?"))?.as_str())?),
+            _ => None,
+        };
+        self.model.func_level_check(f, irange, vrange, rrange, val)
+    }
+    pub fn tran_alevel(&mut self, f: TranFunc, val: f32) -> anyhow::Result<f32> {
+        self.tran_level_check(f, val)?;
+        self.lxi.set_f(format!(":{f}:TRAN:ALEV"), val)
+    }
+    pub fn tran_alevel_q(&mut self, f: TranFunc) -> anyhow::Result<f32> {
+        self.lxi.get_f(format!(":{f}:TRAN:ALEV?"))
+    }
+    pub fn tran_blevel(&mut self, f: TranFunc, val: f32) -> anyhow::Result<f32> {
+        self.tran_level_check(f, val)?;
+        self.lxi.set_f(format!(":{f}:TRAN:BLEV"), val)
+    }
+    pub fn tran_blevel_q(&mut self, f: TranFunc) -> anyhow::Result<f32> {
+        self.lxi.get_f(format!(":{f}:TRAN:BLEV?"))
+    }
+    pub fn tran_width_check(width: f32) -> anyhow::Result<()> {
+        if !width.is_finite() {
+            return Err(anyhow!("Width {width} is not a valid number"));
+        } else if width < TRAN_WIDTH_MIN {
+            return Err(anyhow!("Width {width} too low, min={TRAN_WIDTH_MIN}"));
+        } else if width > TRAN_WIDTH_MAX {
+            return Err(anyhow!("Width {width} too high, max={TRAN_WIDTH_MAX}"));
+        }
+        Ok(())
+    }
+    pub fn tran_awidth(&mut self, f: TranFunc, secs: f32) -> anyhow::Result<f32> {
+        Self::tran_width_check(secs)?;
+        self.lxi.set_f(format!(":{f}:TRAN:AWID"), secs)
+    }
+    pub fn tran_awidth_q(&mut self, f: TranFunc) -> anyhow::Result<f32> {
+        self.lxi.get_f(format!(":{f}:TRAN:AWID?"))
+    }
+    pub fn tran_bwidth(&mut self, f: TranFunc, secs: f32) -> anyhow::Result<f32> {
+        Self::tran_width_check(secs)?;
+        self.lxi.set_f(format!(":{f}:TRAN:BWID"), secs)
+    }
+    pub fn tran_bwidth_q(&mut self, f: TranFunc) -> anyhow::Result<f32> {
+        self.lxi.get_f(format!(":{f}:TRAN:BWID?"))
+    }
+    // rise/fall slews only exist in CC transient mode
+    pub fn tran_slew_p(&mut self, slew: Slew) -> anyhow::Result<()> {
+        Self::slew_check(slew)?;
+        self.lxi.set_s(":CURR:TRAN:SLEW:POS", &slew.to_string())?;
+        Ok(())
+    }
+    pub fn tran_slew_n(&mut self, slew: Slew) -> anyhow::Result<()> {
+        Self::slew_check(slew)?;
+        self.lxi.set_s(":CURR:TRAN:SLEW:NEG", &slew.to_string())?;
+        Ok(())
+    }
+    pub fn tran_slew_p_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":CURR:TRAN:SLEW:POS?")
+    }
+    pub fn tran_slew_n_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f(":CURR:TRAN:SLEW:NEG?")
+    }
+
+    // In pulse and toggle transient modes every trigger moves the load to the other level,
+    // in list mode a trigger starts the run. A bus trigger only works with TrigSource::Bus,
+    // so the step can be timed from the host together with other instruments.
+    pub fn trg_source(&mut self, src: TrigSource) -> anyhow::Result<TrigSource> {
+        self.lxi.send(format!("TRIG:SOUR {src}"))?;
+        Ok(src)
+    }
+    pub fn trg_source_q(&mut self) -> anyhow::Result<TrigSource> {
+        TrigSource::from_str(self.lxi.req("TRIG:SOUR?")?.as_str())
+    }
+    // bus trigger
+    pub fn trg(&mut self) -> anyhow::Result<()> {
+        self.lxi.send("*TRG")
+    }
+    // trigger right now regardless of the source setting
+    pub fn trg_imm(&mut self) -> anyhow::Result<()> {
+        self.lxi.send("TRIG")
+    }
+    // is the load armed and waiting for a trigger
+    pub fn trg_state_q(&mut self) -> anyhow::Result<bool> {
+        self.lxi.get_stateb("TRIG:STAT?")
+    }
+
+    // List mode: the load runs through up to LIST_STEPS_MAX steps with hardware timing
+    pub fn list_func(&mut self, f: TranFunc) -> anyhow::Result<TranFunc> {
+        self.lxi.send(format!(":LIST:MODE {f}"))?;
+        Ok(f)
+    }
+    pub fn list_func_q(&mut self) -> anyhow::Result<TranFunc> {
+        TranFunc::from_str(self.lxi.req(":LIST:MODE?")?.as_str())
+    }
+    pub fn list_irange(&mut self, v: IRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":LIST:IRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn list_irange_q(&mut self) -> anyhow::Result<IRange> {
+        IRange::from_str(self.lxi.req(":LIST:IRANG?")?.as_str())
+    }
+    pub fn list_vrange(&mut self, v: VRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":LIST:VRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn list_vrange_q(&mut self) -> anyhow::Result<VRange> {
+        VRange::from_str(self.lxi.req(":LIST:VRANG?")?.as_str())
+    }
+    pub fn list_rrange(&mut self, v: RRange) -> anyhow::Result<()> {
+        self.lxi.set_s(":LIST:RRANG", &v.to_string())?;
+        Ok(())
+    }
+    pub fn list_rrange_q(&mut self) -> anyhow::Result<RRange> {
+        RRange::from_str(self.lxi.req(":LIST:RRANG?")?.as_str())
+    }
+    // ranges must be set before uploading, the levels are checked against them
+    pub fn list_upload(&mut self, f: TranFunc, steps: &[ListStep]) -> anyhow::Result<()> {
+        if steps.is_empty() || steps.len() > LIST_STEPS_MAX {
+            return Err(anyhow!(
+                "List must have 1..{LIST_STEPS_MAX} steps, got {}",
+                steps.len()
+            ));
+        }
+        for step in steps {
+            self.func_level_check(f, ":LIST", step.level)?;
+            Self::tran_width_check(step.width)?;
+            if let Some(slew) = step.slew {
+                if f != TranFunc::Curr {
+                    return Err(anyhow!("Slew can only be set in CURR list mode"));
+                }
+                Self::slew_check(slew)?;
+            }
+        }
+
+        self.list_func(f)?;
+        self.lxi.send(format!(":LIST:STEP {}", steps.len()))?;
+        for (i, step) in steps.iter().enumerate() {
+            let n = i + 1;
+            self.lxi.send(format!(":LIST:LEV {n},{}", step.level))?;
+            self.lxi.send(format!(":LIST:WID {n},{}", step.width))?;
+            if let Some(slew) = step.slew {
+                self.lxi.send(format!(":LIST:SLEW {n},{slew}"))?;
+            }
+        }
+        Ok(())
+    }
+    pub fn list_steps_q(&mut self) -> anyhow::Result<usize> {
+        Ok(self.lxi.req(":LIST:STEP?")?.trim().parse::<usize>()?)
+    }
+    // how many times the whole list is run
+    pub fn list_count(&mut self, count: u16) -> anyhow::Result<u16> {
+        if count == 0 {
+            return Err(anyhow!("List count must be at least 1"));
+        }
+        self.lxi.send(format!(":LIST:COUN {count}"))?;
+        Ok(count)
+    }
+    pub fn list_count_q(&mut self) -> anyhow::Result<u16> {
+        Ok(self.lxi.req(":LIST:COUN?")?.trim().parse::<u16>()?)
+    }
+    pub fn list_state(&mut self, state: PortState) -> anyhow::Result<PortState> {
+        self.lxi.set_state(":LIST:STAT", state)
+    }
+    pub fn list_state_q(&mut self) -> anyhow::Result<PortState> {
+        self.lxi.get_state(":LIST:STAT?")
+    }
+    // enter list mode, the input must be on. With bus trigger source the run is
+    // started right away, otherwise it waits for a manual or external trigger.
+    pub fn list_start(&mut self) -> anyhow::Result<()> {
+        self.list_state(PortState::On)?;
+        if self.trg_source_q()? == TrigSource::Bus {
+            self.trg()?;
+        }
+        Ok(())
+    }
+    pub fn list_stop(&mut self) -> anyhow::Result<()> {
+        self.list_state(PortState::Off)?;
+        Ok(())
+    }
+    pub fn list_progress_q(&mut self) -> anyhow::Result<ListProgress> {
+        Ok(ListProgress {
+            running: self.list_state_q()? == PortState::On,
+            step: self.lxi.req(":LIST:STAT:STEP?")?.trim().parse::<usize>()?,
+            count: self.lxi.req(":LIST:STAT:COUN?")?.trim().parse::<u16>()?,
+        })
+    }
+
+    // Battery test: discharge until one of the enabled stop conditions is met,
+    // the load switches its input off by itself at that point.
+    pub fn batt_setup(&mut self, cfg: &BattConfig) -> anyhow::Result<()> {
+        // nothing is sent unless the whole config is valid
+        cfg.validate(self.model)?;
+
+        self.lxi.send(format!(":BATT:FUNC {}", cfg.func))?;
+        self.lxi.set_s(":BATT:IRANG", &cfg.irange.to_string())?;
+        self.lxi.set_s(":BATT:VRANG", &cfg.vrange.to_string())?;
+        if let Some(rrange) = cfg.rrange {
+            self.lxi.set_s(":BATT:RRANG", &rrange.to_string())?;
+        }
+        self.lxi.set_f(":BATT:LEV", cfg.level)?;
+
+        match cfg.stop_volt {
+            Some(v) => {
+                self.lxi.set_f(":BATT:VOLT", v)?;
+                self.lxi.set_state(":BATT:VOLT:STAT", PortState::On)?;
+            }
+            None => {
+                self.lxi.set_state(":BATT:VOLT:STAT", PortState::Off)?;
+            }
+        }
+        match cfg.stop_cap {
+            Some(c) => {
+                self.lxi.set_f(":BATT:CAP", c)?;
+                self.lxi.set_state(":BATT:CAP:STAT", PortState::On)?;
+            }
+            None => {
+                self.lxi.set_state(":BATT:CAP:STAT", PortState::Off)?;
+            }
+        }
+        match cfg.stop_time {
+            Some(t) => {
+                self.lxi.send(format!(":BATT:TIM {}", t.as_secs()))?;
+                self.lxi.set_state(":BATT:TIM:STAT", PortState::On)?;
+            }
+            None => {
+                self.lxi.set_state(":BATT:TIM:STAT", PortState::Off)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn batt_state(&mut self, state: PortState) -> anyhow::Result<PortState> {
+        self.lxi.set_state(":BATT:STAT", state)
+    }
+    pub fn batt_start(&mut self) -> anyhow::Result<()> {
+        self.batt = BattEnergy::default();
+        self.batt_state(PortState::On)?;
+        self.input_on()?;
+        Ok(())
+    }
+    pub fn batt_stop(&mut self) -> anyhow::Result<()> {
+        self.input_off()?;
+        self.batt_state(PortState::Off)?;
+        Ok(())
+    }
+    // the energy counts the power of the last batt_progress_q() up to the end
+    pub fn batt_result_q(&mut self) -> anyhow::Result<BattResult> {
+        let time = time::Duration::try_from_secs_f32(self.lxi.get_f(":BATT:DISCHA:TIM?")?)?;
+        Ok(BattResult {
+            capacity: self.lxi.get_f(":BATT:DISCHA:CAP?")?,
+            energy: self.batt.at(time),
+            time,
+        })
+    }
+    // poll this regularly during the test, the energy is integrated from it
+    pub fn batt_progress_q(&mut self) -> anyhow::Result<BattProgress> {
+        let running = self.input_q()? == PortState::On;
+        let (volt, curr, powr) = (self.volt_m()?, self.curr_m()?, self.powr_m()?);
+        let mut result = self.batt_result_q()?;
+        // once the test has stopped the power is 0, keep the last one up to the end
+        if running {
+            self.batt.add(powr, result.time);
+            result.energy = self.batt.energy;
+        }
+        Ok(BattProgress {
+            running,
+            volt,
+            curr,
+            powr,
+            result,
+        })
+    }
+
+    // OCP/OPP tests: ramp the current or power from start to end until the
+    // source voltage falls below the trip threshold, then report the trip point.
+    pub fn prot_test_setup(
+        &mut self,
+        test: ProtTest,
+        cfg: &ProtTestConfig,
+    ) -> anyhow::Result<()> {
+        let f = match test {
+            ProtTest::Ocp => TranFunc::Curr,
+            ProtTest::Opp => TranFunc::Powr,
+        };
+        for level in [cfg.start, cfg.end] {
+            self.model.func_level_check(f, cfg.irange, cfg.vrange, None, level)?;
+        }
+        if cfg.end <= cfg.start {
+            return Err(anyhow!("{test} end {} must be above start {}", cfg.end, cfg.start));
+        }
+        if !(cfg.step > 0.0 && cfg.step <= cfg.end - cfg.start) {
+            return Err(anyhow!("{test} step {} out of range", cfg.step));
+        }
+        level_check("Trip voltage", cfg.trip_volt, cfg.vrange as u32 as f32)?;
+
+        self.lxi.set_s(&format!(":{test}:IRANG"), &cfg.irange.to_string())?;
+        self.lxi.set_s(&format!(":{test}:VRANG"), &cfg.vrange.to_string())?;
+        self.lxi.set_f(format!(":{test}:STAR"), cfg.start)?;
+        self.lxi.set_f(format!(":{test}:STEP"), cfg.step)?;
+        self.lxi.set_f(format!(":{test}:STEP:DEL"), cfg.step_delay.as_secs_f32())?;
+        self.lxi.set_f(format!(":{test}:END"), cfg.end)?;
+        self.lxi.set_f(format!(":{test}:VOLT"), cfg.trip_volt)?;
+        Ok(())
+    }
+    pub fn prot_test_state(
+        &mut self,
+        test: ProtTest,
+        state: PortState,
+    ) -> anyhow::Result<PortState> {
+        self.lxi.set_state(format!(":{test}:STAT"), state)
+    }
+    pub fn prot_test_state_q(&mut self, test: ProtTest) -> anyhow::Result<PortState> {
+        self.lxi.get_state(format!(":{test}:STAT?"))
+    }
+    pub fn prot_test_start(&mut self, test: ProtTest) -> anyhow::Result<()> {
+        self.prot_test_state(test, PortState::On)?;
+        self.input_on()?;
+        Ok(())
+    }
+    pub fn prot_test_stop(&mut self, test: ProtTest) -> anyhow::Result<()> {
+        self.input_off()?;
+        self.prot_test_state(test, PortState::Off)?;
+        Ok(())
+    }
+    pub fn prot_test_result_q(&mut self, test: ProtTest) -> anyhow::Result<ProtTestResult> {
+        ProtTestResult::from_str(self.lxi.req(format!(":{test}:RES?"))?.as_str())
+    }
+    // Poll until the test has finished and return the result. The test is
+    // stopped and the input switched off however this ends.
+    pub fn prot_test_run(
+        &mut self,
+        test: ProtTest,
+        poll: time::Duration,
+        timeout: time::Duration,
+    ) -> anyhow::Result<ProtTestResult> {
+        let res = self
+            .prot_test_start(test)
+            .and_then(|_| self.prot_test_wait(test, poll, timeout));
+        let stop = self.prot_test_stop(test);
+        let res = res?;
+        stop?;
+        Ok(res)
+    }
+    fn prot_test_wait(
+        &mut self,
+        test: ProtTest,
+        poll: time::Duration,
+        timeout: time::Duration,
+    ) -> anyhow::Result<ProtTestResult> {
+        let start = time::Instant::now();
+        while self.prot_test_state_q(test)? == PortState::On {
+            if start.elapsed() > timeout {
+                return Err(anyhow!("{test} test did not finish in {timeout:?}"));
+            }
+            thread::sleep(poll);
+        }
+        self.prot_test_result_q(test)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProtTest {
+    Ocp,
+    Opp,
+}
+
+impl Display for ProtTest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Ocp => "OCP",
+            Self::Opp => "OPP",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProtTestConfig {
+    pub irange: IRange,
+    pub vrange: VRange,
+    // A for OCP, W for OPP
+    pub start: f32,
+    pub step: f32,
+    pub end: f32,
+    pub step_delay: time::Duration,
+    // the protection is considered tripped when the voltage falls below this
+    pub trip_volt: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProtTestResult {
+    pub tripped: bool,
+    // A for OCP, W for OPP
+    pub trip_level: f32,
+}
+
+impl FromStr for ProtTestResult {
+    type Err = anyhow::Error;
+    // response is "<tripped>,<level>", e.g. "1,2.345"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once(',') {
+            Some((t, l)) => Ok(Self {
+                tripped: t.trim() == "1",
+                trip_level: l.trim().parse::<f32>()?,
+            }),
+            None => Err(anyhow!("Invalid test result format: {s}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BattFunc {
+    Curr,
+    Powr,
+    Res,
+}
+
+impl Display for BattFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        TranFunc::from(*self).fmt(f)
+    }
+}
+
+impl From<BattFunc> for TranFunc {
+    fn from(f: BattFunc) -> Self {
+        match f {
+            BattFunc::Curr => TranFunc::Curr,
+            BattFunc::Powr => TranFunc::Powr,
+            BattFunc::Res => TranFunc::Res,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BattConfig {
+    pub func: BattFunc,
+    // A, W or ohms depending on func
+    pub level: f32,
+    pub irange: IRange,
+    pub vrange: VRange,
+    // CR mode only
+    pub rrange: Option<RRange>,
+    // stop conditions, at least one must be set
+    pub stop_volt: Option<f32>,
+    pub stop_cap: Option<f32>,
+    pub stop_time: Option<time::Duration>,
+}
+
+impl BattConfig {
+    pub fn validate(&self, model: SDL1000XModel) -> anyhow::Result<()> {
+        model.func_level_check(self.func.into(), self.irange, self.vrange, self.rrange, self.level)?;
+        if self.func != BattFunc::Res && self.rrange.is_some() {
+            return Err(anyhow!("Resistance range is only used in CR mode"));
+        }
+
+        if self.stop_volt.is_none() && self.stop_cap.is_none() && self.stop_time.is_none() {
+            return Err(anyhow!("Battery test needs at least one stop condition"));
+        }
+        if let Some(v) = self.stop_volt {
+            level_check("Stop voltage", v, self.vrange as u32 as f32)?;
+        }
+        if let Some(c) = self.stop_cap {
+            if !(c > 0.0 && c.is_finite()) {
+                return Err(anyhow!("Stop capacity {c} must be positive"));
+            }
+        }
+        if let Some(t) = self.stop_time {
+            if t.as_secs() == 0 {
+                return Err(anyhow!("Stop time {t:?} must be at least 1s"));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BattResult {
+    // Ah
+    pub capacity: f32,
+    // Wh
+    pub energy: f32,
+    pub time: time::Duration,
+}
+
+// The load reports the capacity but not the energy, it is integrated from the
+// power at each progress poll over the test time the load reports.
+#[derive(Clone, Copy, Debug, Default)]
+struct BattEnergy {
+    // Wh
+    energy: f32,
+    // power and test time of the last poll
+    powr: f32,
+    time: time::Duration,
+}
+
+impl BattEnergy {
+    fn add(&mut self, powr: f32, time: time::Duration) {
+        self.energy += powr * time.saturating_sub(self.time).as_secs_f32() / 3600.0;
+        self.powr = powr;
+        self.time = time;
+    }
+    // the power of the last poll up to the given test time
+    fn at(&self, time: time::Duration) -> f32 {
+        self.energy + self.powr * time.saturating_sub(self.time).as_secs_f32() / 3600.0
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct BattProgress {
+    pub running: bool,
+    pub volt: f32,
+    pub curr: f32,
+    pub powr: f32,
+    pub result: BattResult,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ListStep {
+    pub level: f32,
+    // seconds
+    pub width: f32,
+    // CC mode only
+    pub slew: Option<Slew>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ListProgress {
+    pub running: bool,
+    pub step: usize,
+    pub count: u16,
+}
+
+// single input, everything is measurable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadMeas {
+    Volt,
+    Curr,
+    Powr,
+    Res,
+    Ext,
+}
+
+impl From<LoadMeas> for Meas {
+    fn from(m: LoadMeas) -> Self {
+        match m {
+            LoadMeas::Volt => Meas::Volt,
+            LoadMeas::Curr => Meas::Curr,
+            LoadMeas::Powr => Meas::Powr,
+            LoadMeas::Res => Meas::Res,
+            LoadMeas::Ext => Meas::Ext,
+        }
+    }
+}
+
+impl Display for LoadMeas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Meas::from(*self).fmt(f)
+    }
+}
+
+// quantities the waveform display can show
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaveMeas {
+    Curr,
+    Volt,
+    Powr,
+    Res,
+}
+
+impl From<WaveMeas> for Meas {
+    fn from(m: WaveMeas) -> Self {
+        match m {
+            WaveMeas::Curr => Meas::Curr,
+            WaveMeas::Volt => Meas::Volt,
+            WaveMeas::Powr => Meas::Powr,
+            WaveMeas::Res => Meas::Res,
+        }
+    }
+}
+
+impl Display for WaveMeas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Curr => "CURR",
+            Self::Volt => "VOLT",
+            Self::Powr => "POW",
+            Self::Res => "RES",
+        })
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -250,6 +1269,75 @@ impl Display for Curr {
 }
 
 #[derive(Clone, Copy, Debug)]
+pub enum Volt {
+    Min,
+    Max,
+    Default,
+    V(f32),
+}
+
+impl Display for Volt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s;
+        f.write_str(match *self {
+            Self::Min => "MIN",
+            Self::Max => "MAX",
+            Self::Default => "DEF",
+            Self::V(v) => {
+                s = v.to_string();
+                s.as_str()
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Powr {
+    Min,
+    Max,
+    Default,
+    W(f32),
+}
+
+impl Display for Powr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s;
+        f.write_str(match *self {
+            Self::Min => "MIN",
+            Self::Max => "MAX",
+            Self::Default => "DEF",
+            Self::W(w) => {
+                s = w.to_string();
+                s.as_str()
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Res {
+    Min,
+    Max,
+    Default,
+    Ohm(f32),
+}
+
+impl Display for Res {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s;
+        f.write_str(match *self {
+            Self::Min => "MIN",
+            Self::Max => "MAX",
+            Self::Default => "DEF",
+            Self::Ohm(r) => {
+                s = r.to_string();
+                s.as_str()
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum IRange {
     I5A = 5,
     I30A = 30,
@@ -272,7 +1360,7 @@ impl FromStr for IRange {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum VRange {
     V36V = 36,
     V150V = 150,
@@ -295,7 +1383,7 @@ impl FromStr for VRange {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RRange {
     Low,
     Middle,
@@ -314,6 +1402,39 @@ impl Display for RRange {
     }
 }
 
+impl FromStr for RRange {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "LOW" => Ok(Self::Low),
+            "MIDDLE" => Ok(Self::Middle),
+            "HIGH" => Ok(Self::High),
+            "UPPER" => Ok(Self::Upper),
+            x => Err(anyhow!("Unknown RRange {x}")),
+        }
+    }
+}
+
+impl RRange {
+    // (min, max) in ohms
+    pub fn limits(&self) -> (f32, f32) {
+        match *self {
+            Self::Low => (0.03, 10.0),
+            Self::Middle => (0.1, 100.0),
+            Self::High => (1.0, 1000.0),
+            Self::Upper => (10.0, 10000.0),
+        }
+    }
+    pub fn check(&self, val: f32) -> anyhow::Result<()> {
+        let (res_min, res_max) = self.limits();
+        if (res_min..=res_max).contains(&val) {
+            Ok(())
+        } else {
+            Err(anyhow!("Resistance {val} out of {self} range {res_min}..{res_max}"))
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Slew {
     Min,
@@ -340,6 +1461,108 @@ impl Display for Slew {
     }
 }
 
+// functions that can be controlled through the analog input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExtFunc {
+    Curr,
+    Volt,
+}
+
+impl Display for ExtFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Curr => "CURR",
+            Self::Volt => "VOLT",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrigSource {
+    Manual,
+    External,
+    Bus,
+}
+
+impl Display for TrigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Manual => "MAN",
+            Self::External => "EXT",
+            Self::Bus => "BUS",
+        })
+    }
+}
+
+impl FromStr for TrigSource {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "MANUAL" | "MAN" => Ok(Self::Manual),
+            "EXTERNAL" | "EXT" => Ok(Self::External),
+            "BUS" => Ok(Self::Bus),
+            x => Err(anyhow!("Unknown trigger source {x}")),
+        }
+    }
+}
+
+// functions that have transient and list modes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TranFunc {
+    Curr,
+    Volt,
+    Powr,
+    Res,
+}
+
+impl Display for TranFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Curr => "CURR",
+            Self::Volt => "VOLT",
+            Self::Powr => "POW",
+            Self::Res => "RES",
+        })
+    }
+}
+
+impl From<TranFunc> for Func {
+    fn from(f: TranFunc) -> Self {
+        match f {
+            TranFunc::Curr => Func::Curr,
+            TranFunc::Volt => Func::Volt,
+            TranFunc::Powr => Func::Powr,
+            TranFunc::Res => Func::Res,
+        }
+    }
+}
+
+impl TryFrom<Func> for TranFunc {
+    type Error = anyhow::Error;
+    fn try_from(f: Func) -> Result<Self, Self::Error> {
+        match f {
+            Func::Curr => Ok(TranFunc::Curr),
+            Func::Volt => Ok(TranFunc::Volt),
+            Func::Powr => Ok(TranFunc::Powr),
+            Func::Res => Ok(TranFunc::Res),
+            x => Err(anyhow!("Function {x} has no level")),
+        }
+    }
+}
+
+impl FromStr for TranFunc {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CURRENT" => Ok(Self::Curr),
+            "VOLTAGE" => Ok(Self::Volt),
+            "POWER" => Ok(Self::Powr),
+            "RESISTANCE" => Ok(Self::Res),
+            x => Err(anyhow!("Unknown transient function {x}")),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Transient {
     Continuous,
@@ -369,4 +1592,105 @@ impl FromStr for Transient {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prot_test_result_from_str() {
+        let r = ProtTestResult::from_str("1,2.345").unwrap();
+        assert!(r.tripped);
+        assert_eq!(r.trip_level, 2.345);
+        let r = ProtTestResult::from_str(" 0, 0.000\n").unwrap();
+        assert!(!r.tripped);
+        assert_eq!(r.trip_level, 0.0);
+        assert!(ProtTestResult::from_str("1").is_err());
+        assert!(ProtTestResult::from_str("1,abc").is_err());
+    }
+
+    #[test]
+    fn func_level() {
+        let model = SDL1000XModel::SDL1020X;
+        let check = |f, val| model.func_level_check(f, IRange::I30A, VRange::V150V, None, val);
+        assert!(check(TranFunc::Curr, 30.0).is_ok());
+        assert!(check(TranFunc::Curr, 30.1).is_err());
+        assert!(check(TranFunc::Volt, 150.0).is_ok());
+        assert!(check(TranFunc::Volt, f32::NAN).is_err());
+        // the model limit is below the ranges
+        assert!(check(TranFunc::Powr, 200.0).is_ok());
+        assert!(check(TranFunc::Powr, 201.0).is_err());
+        assert!(check(TranFunc::Res, 10.0).is_err());
+
+        let check = |rrange, val| {
+            model.func_level_check(TranFunc::Res, IRange::I5A, VRange::V36V, Some(rrange), val)
+        };
+        assert!(check(RRange::Low, 0.03).is_ok());
+        assert!(check(RRange::Low, 10.0).is_ok());
+        assert!(check(RRange::Low, 0.02).is_err());
+        assert!(check(RRange::Upper, 10001.0).is_err());
+        assert!(check(RRange::Middle, f32::NAN).is_err());
+    }
+
+    #[test]
+    fn batt_energy() {
+        let secs = time::Duration::from_secs;
+        let mut e = BattEnergy::default();
+        e.add(36.0, secs(100));
+        e.add(18.0, secs(300));
+        assert_eq!(e.energy, 2.0);
+        // the test ended 200s after the last poll
+        assert_eq!(e.at(secs(500)), 3.0);
+        assert_eq!(e.at(secs(300)), 2.0);
+    }
+
+    #[test]
+    fn batt_config_validate() {
+        let model = SDL1000XModel::SDL1020X;
+        let cc = BattConfig {
+            func: BattFunc::Curr,
+            level: 1.0,
+            irange: IRange::I5A,
+            vrange: VRange::V36V,
+            rrange: None,
+            stop_volt: Some(3.0),
+            stop_cap: None,
+            stop_time: None,
+        };
+        assert!(cc.validate(model).is_ok());
+
+        let cfg = BattConfig {
+            stop_volt: None,
+            ..cc
+        };
+        assert!(cfg.validate(model).is_err());
+        let cfg = BattConfig {
+            stop_volt: Some(40.0),
+            ..cc
+        };
+        assert!(cfg.validate(model).is_err());
+        let cfg = BattConfig {
+            stop_cap: Some(0.0),
+            ..cc
+        };
+        assert!(cfg.validate(model).is_err());
+        let cfg = BattConfig {
+            level: 6.0,
+            ..cc
+        };
+        assert!(cfg.validate(model).is_err());
+        let cfg = BattConfig {
+            func: BattFunc::Res,
+            level: 5.0,
+            rrange: Some(RRange::Low),
+            ..cc
+        };
+        assert!(cfg.validate(model).is_ok());
+        let cfg = BattConfig {
+            level: f32::NAN,
+            ..cfg
+        };
+        assert!(cfg.validate(model).is_err());
+    }
+}
+
 // EOF
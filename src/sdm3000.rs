@@ -0,0 +1,417 @@
+// sdm3000.rs
+#![allow(dead_code)]
+
+use std::{fmt, fmt::Display, net::ToSocketAddrs, str::FromStr, thread, time};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// https://int.siglent.com/upload_file/user/SDM3065X/SDM3065X_RemoteManual_RC06035-E01A.pdf
+
+// integration times in power line cycles
+const NPLC_VALUES: [f32; 6] = [0.005, 0.05, 0.5, 1.0, 10.0, 100.0];
+const SAMPLES_MAX: u32 = 10000;
+const FETCH_POLL: time::Duration = time::Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DmmFunc {
+    DcVolt,
+    DcCurr,
+    Res,
+    // 4-wire resistance
+    FRes,
+}
+
+impl Display for DmmFunc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::DcVolt => "VOLT:DC",
+            Self::DcCurr => "CURR:DC",
+            Self::Res => "RES",
+            Self::FRes => "FRES",
+        })
+    }
+}
+
+impl FromStr for DmmFunc {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // FUNC? returns the name in quotes and without the DC suffix
+        match s.trim().trim_matches('"') {
+            "VOLT" | "VOLT:DC" => Ok(Self::DcVolt),
+            "CURR" | "CURR:DC" => Ok(Self::DcCurr),
+            "RES" => Ok(Self::Res),
+            "FRES" => Ok(Self::FRes),
+            x => Err(anyhow!("Unknown DMM function {x}")),
+        }
+    }
+}
+
+impl DmmFunc {
+    // full scale of each range, in V, A or ohms
+    pub fn ranges(&self) -> &'static [f32] {
+        match *self {
+            Self::DcVolt => &[0.2, 2.0, 20.0, 200.0, 1000.0],
+            Self::DcCurr => &[0.0002, 0.002, 0.02, 0.2, 2.0, 10.0],
+            Self::Res | Self::FRes => &[200.0, 2e3, 20e3, 200e3, 2e6, 10e6, 100e6],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DmmRange {
+    Auto,
+    // the smallest range that fits the value is used
+    Fixed(f32),
+}
+
+impl Display for DmmRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Auto => f.write_str("AUTO"),
+            Self::Fixed(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DmmTrigSource {
+    Immediate,
+    External,
+    Bus,
+}
+
+impl Display for DmmTrigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Immediate => "IMM",
+            Self::External => "EXT",
+            Self::Bus => "BUS",
+        })
+    }
+}
+
+impl FromStr for DmmTrigSource {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "IMMEDIATE" | "IMM" => Ok(Self::Immediate),
+            "EXTERNAL" | "EXT" => Ok(Self::External),
+            "BUS" => Ok(Self::Bus),
+            x => Err(anyhow!("Unknown trigger source {x}")),
+        }
+    }
+}
+
+// comma separated readings, e.g. "+1.23456789E+00,+1.23456790E+00"
+fn parse_readings(data: &str) -> anyhow::Result<Vec<f32>> {
+    data.trim()
+        .split(',')
+        .map(|x| {
+            x.trim()
+                .parse::<f32>()
+                .map_err(|e| anyhow!("Invalid reading {x:?}: {e}"))
+        })
+        .collect()
+}
+
+pub struct SDM3000 {
+    pub lxi: StdLxi,
+}
+
+impl SDM3000 {
+    pub fn new<S, H>(name: S, host: H) -> anyhow::Result<Self>
+        where
+            S: AsRef<str>,
+            H: fmt::Display + AsRef<str> + ToSocketAddrs,
+            Self: Sized,
+    {
+        Ok(Self {
+            lxi: StdLxi::new(name, host)?,
+        })
+    }
+
+    pub fn idn_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req("*IDN?")
+    }
+    pub fn error_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req("SYST:ERR?")
+    }
+    pub fn rst(&mut self) -> anyhow::Result<()> {
+        self.lxi.send("*RST")
+    }
+
+    pub fn func(&mut self, f: DmmFunc) -> anyhow::Result<DmmFunc> {
+        self.lxi.send(format!("FUNC \"{f}\""))?;
+        Ok(f)
+    }
+    pub fn func_q(&mut self) -> anyhow::Result<DmmFunc> {
+        DmmFunc::from_str(self.lxi.req("FUNC?")?.as_str())
+    }
+
+    pub fn range_check(f: DmmFunc, r: DmmRange) -> anyhow::Result<()> {
+        if let DmmRange::Fixed(v) = r {
+            let max = f.ranges()[f.ranges().len() - 1];
+            if !(v > 0.0 && v <= max) {
+                return Err(anyhow!("{f} range {v} invalid, max={max}"));
+            }
+        }
+        Ok(())
+    }
+    pub fn range(&mut self, f: DmmFunc, r: DmmRange) -> anyhow::Result<DmmRange> {
+        Self::range_check(f, r)?;
+        match r {
+            DmmRange::Auto => {
+                self.lxi.set_state(format!("{f}:RANG:AUTO"), PortState::On)?;
+            }
+            DmmRange::Fixed(v) => {
+                self.lxi.set_f(format!("{f}:RANG"), v)?;
+            }
+        }
+        Ok(r)
+    }
+    pub fn range_q(&mut self, f: DmmFunc) -> anyhow::Result<DmmRange> {
+        if self.lxi.get_stateb(format!("{f}:RANG:AUTO?"))? {
+            Ok(DmmRange::Auto)
+        } else {
+            Ok(DmmRange::Fixed(self.lxi.get_f(format!("{f}:RANG?"))?))
+        }
+    }
+
+    // select the function and range in one go
+    pub fn conf(&mut self, f: DmmFunc, r: DmmRange) -> anyhow::Result<()> {
+        self.func(f)?;
+        self.range(f, r)?;
+        Ok(())
+    }
+
+    // longer integration averages out more noise but takes more time
+    pub fn nplc(&mut self, f: DmmFunc, n: f32) -> anyhow::Result<f32> {
+        if !NPLC_VALUES.contains(&n) {
+            return Err(anyhow!("Invalid NPLC {n}, must be one of {NPLC_VALUES:?}"));
+        }
+        self.lxi.set_f(format!("{f}:NPLC"), n)
+    }
+    pub fn nplc_q(&mut self, f: DmmFunc) -> anyhow::Result<f32> {
+        self.lxi.get_f(format!("{f}:NPLC?"))
+    }
+
+    pub fn trg_source(&mut self, src: DmmTrigSource) -> anyhow::Result<DmmTrigSource> {
+        self.lxi.send(format!("TRIG:SOUR {src}"))?;
+        Ok(src)
+    }
+    pub fn trg_source_q(&mut self) -> anyhow::Result<DmmTrigSource> {
+        DmmTrigSource::from_str(self.lxi.req("TRIG:SOUR?")?.as_str())
+    }
+    // seconds between the trigger and the first sample
+    pub fn trg_delay(&mut self, delay: f32) -> anyhow::Result<f32> {
+        level_check("Trigger delay", delay, 3600.0)?;
+        self.lxi.set_f("TRIG:DEL", delay)
+    }
+    pub fn trg_delay_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f("TRIG:DEL?")
+    }
+    // bus trigger
+    pub fn trg(&mut self) -> anyhow::Result<()> {
+        self.lxi.send("*TRG")
+    }
+
+    // readings taken per trigger
+    pub fn sample_count(&mut self, n: u32) -> anyhow::Result<u32> {
+        if n == 0 || n > SAMPLES_MAX {
+            return Err(anyhow!("Invalid sample count {n}, must be 1..{SAMPLES_MAX}"));
+        }
+        self.lxi.send(format!("SAMP:COUN {n}"))?;
+        Ok(n)
+    }
+    pub fn sample_count_q(&mut self) -> anyhow::Result<u32> {
+        Ok(self.lxi.req("SAMP:COUN?")?.trim().parse::<f32>()? as u32)
+    }
+
+    // arm the meter, the readings are taken when the trigger arrives
+    pub fn init(&mut self) -> anyhow::Result<()> {
+        self.lxi.send("INIT")
+    }
+    // readings waiting in the buffer
+    pub fn points_q(&mut self) -> anyhow::Result<u32> {
+        Ok(self.lxi.req("DATA:POIN?")?.trim().parse::<u32>()?)
+    }
+    pub fn fetch_q(&mut self) -> anyhow::Result<Vec<f32>> {
+        parse_readings(&self.lxi.req("FETC?")?)
+    }
+
+    // single reading with the current setup
+    pub fn read_q(&mut self) -> anyhow::Result<f32> {
+        self.lxi.get_f("READ?")
+    }
+    // single reading, the meter is set to the function with autorange first
+    pub fn meas_q(&mut self, f: DmmFunc) -> anyhow::Result<f32> {
+        self.lxi.get_f(format!("MEAS:{f}?"))
+    }
+    pub fn volt_m(&mut self) -> anyhow::Result<f32> {
+        self.meas_q(DmmFunc::DcVolt)
+    }
+    pub fn curr_m(&mut self) -> anyhow::Result<f32> {
+        self.meas_q(DmmFunc::DcCurr)
+    }
+    pub fn res_m(&mut self) -> anyhow::Result<f32> {
+        self.meas_q(DmmFunc::Res)
+    }
+
+    // Take n readings into the buffer and fetch them once all are in.
+    // With TRIG:SOUR BUS the trigger is sent from here.
+    pub fn read_buffered(&mut self, n: u32, timeout: time::Duration) -> anyhow::Result<Vec<f32>> {
+        self.sample_count(n)?;
+        self.init()?;
+        if self.trg_source_q()? == DmmTrigSource::Bus {
+            self.trg()?;
+        }
+        let start = time::Instant::now();
+        loop {
+            let points = self.points_q()?;
+            if points >= n {
+                break;
+            }
+            if start.elapsed() > timeout {
+                return Err(anyhow!("Timeout, got {points} of {n} readings"));
+            }
+            thread::sleep(FETCH_POLL);
+        }
+        self.fetch_q()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn readings() {
+        assert_eq!(
+            parse_readings("+1.23456789E+00,-2.50000000E-03\n").unwrap(),
+            [1.234_567_9, -0.0025]
+        );
+        assert_eq!(parse_readings("+1.0E+01").unwrap(), [10.0]);
+        assert!(parse_readings("").is_err());
+        assert!(parse_readings("+1.0E+00,,+2.0E+00").is_err());
+        assert!(parse_readings("+9.9E+37,OVLD").is_err());
+    }
+
+    #[test]
+    fn func_round_trip() {
+        for f in [DmmFunc::DcVolt, DmmFunc::DcCurr, DmmFunc::Res, DmmFunc::FRes] {
+            assert_eq!(DmmFunc::from_str(&f.to_string()).unwrap(), f);
+        }
+        // as returned by FUNC?
+        assert_eq!(DmmFunc::from_str("\"VOLT\"\n").unwrap(), DmmFunc::DcVolt);
+        assert_eq!(DmmFunc::from_str("\"CURR\"").unwrap(), DmmFunc::DcCurr);
+        assert!(DmmFunc::from_str("\"FREQ\"").is_err());
+    }
+
+    #[test]
+    fn trig_source() {
+        for src in [DmmTrigSource::Immediate, DmmTrigSource::External, DmmTrigSource::Bus] {
+            assert_eq!(DmmTrigSource::from_str(&src.to_string()).unwrap(), src);
+        }
+        assert_eq!(DmmTrigSource::from_str("IMMEDIATE\n").unwrap(), DmmTrigSource::Immediate);
+        assert!(DmmTrigSource::from_str("TIMER").is_err());
+    }
+
+    #[test]
+    fn range() {
+        assert!(SDM3000::range_check(DmmFunc::DcVolt, DmmRange::Auto).is_ok());
+        assert!(SDM3000::range_check(DmmFunc::DcVolt, DmmRange::Fixed(20.0)).is_ok());
+        assert!(SDM3000::range_check(DmmFunc::DcVolt, DmmRange::Fixed(1000.0)).is_ok());
+        assert!(SDM3000::range_check(DmmFunc::DcVolt, DmmRange::Fixed(1001.0)).is_err());
+        assert!(SDM3000::range_check(DmmFunc::DcCurr, DmmRange::Fixed(20.0)).is_err());
+        assert!(SDM3000::range_check(DmmFunc::FRes, DmmRange::Fixed(100e6)).is_ok());
+        assert!(SDM3000::range_check(DmmFunc::Res, DmmRange::Fixed(0.0)).is_err());
+        assert!(SDM3000::range_check(DmmFunc::Res, DmmRange::Fixed(-200.0)).is_err());
+        assert!(SDM3000::range_check(DmmFunc::DcVolt, DmmRange::Fixed(f32::NAN)).is_err());
+    }
+
+    // A meter on localhost with BUS trigger that fills its buffer after *TRG,
+    // half of the readings per DATA:POIN? poll. With stuck set it never triggers.
+    fn simulator(stuck: bool) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let cmds = log.clone();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut out = stream.try_clone().unwrap();
+            let (mut count, mut points, mut triggered) = (1u32, 0, false);
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                let cmd = line.trim().to_string();
+                let resp = match cmd.as_str() {
+                    "TRIG:SOUR?" => Some("BUS".to_string()),
+                    "*TRG" => {
+                        triggered = !stuck;
+                        None
+                    }
+                    "DATA:POIN?" => {
+                        if triggered {
+                            points = (points + count.div_ceil(2)).min(count);
+                        }
+                        Some(points.to_string())
+                    }
+                    "FETC?" => Some(
+                        (0..points)
+                            .map(|i| format!("+{i}.00000000E-03"))
+                            .collect::<Vec<_>>()
+                            .join(","),
+                    ),
+                    c => {
+                        if let Some(n) = c.strip_prefix("SAMP:COUN ") {
+                            count = n.parse().unwrap();
+                        }
+                        None
+                    }
+                };
+                cmds.lock().unwrap().push(cmd);
+                if let Some(resp) = resp {
+                    out.write_all(format!("{resp}\n").as_bytes()).unwrap();
+                }
+            }
+        });
+        (addr, log)
+    }
+
+    #[test]
+    fn read_buffered() {
+        let (addr, log) = simulator(false);
+        let mut dmm = SDM3000::new("DMM", addr).unwrap();
+        let r = dmm.read_buffered(5, time::Duration::from_secs(2)).unwrap();
+        assert_eq!(r, [0.0, 0.001, 0.002, 0.003, 0.004]);
+        assert!(dmm.read_buffered(0, time::Duration::from_secs(2)).is_err());
+
+        let cmds = log.lock().unwrap();
+        assert_eq!(
+            cmds[..4],
+            ["SAMP:COUN 5", "INIT", "TRIG:SOUR?", "*TRG"].map(String::from)
+        );
+        assert_eq!(cmds.iter().filter(|c| *c == "DATA:POIN?").count(), 2);
+        assert_eq!(cmds.last().unwrap(), "FETC?");
+    }
+
+    #[test]
+    fn read_buffered_timeout() {
+        let (addr, _log) = simulator(true);
+        let mut dmm = SDM3000::new("DMM", addr).unwrap();
+        let e = dmm
+            .read_buffered(5, time::Duration::from_millis(250))
+            .unwrap_err();
+        assert_eq!(e.to_string(), "Timeout, got 0 of 5 readings");
+    }
+}
+
+// EOF
@@ -0,0 +1,435 @@
+// sds.rs
+#![allow(dead_code)]
+
+use std::{fmt, fmt::Display, fs, net::ToSocketAddrs, path::Path, str::FromStr, thread, time};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// https://int.siglent.com/upload_file/user/SDS1000X-E/SDS1000X-E_ProgrammingGuide_PG01-E02D.pdf
+
+// horizontal divisions on the screen
+const GRID_X: f32 = 14.0;
+// ADC codes per vertical division
+const CODES_PER_DIV: f32 = 25.0;
+const ACQ_POLL: time::Duration = time::Duration::from_millis(100);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdsCh {
+    Ch1,
+    Ch2,
+    Ch3,
+    Ch4,
+}
+
+impl From<SdsCh> for Ch {
+    fn from(c: SdsCh) -> Self {
+        match c {
+            SdsCh::Ch1 => Ch::Ch1,
+            SdsCh::Ch2 => Ch::Ch2,
+            SdsCh::Ch3 => Ch::Ch3,
+            SdsCh::Ch4 => Ch::Ch4,
+        }
+    }
+}
+
+impl Display for SdsCh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Ch::from(*self).fmt(f)
+    }
+}
+
+impl SdsCh {
+    // command header, C1..C4
+    fn hdr(&self) -> &'static str {
+        match *self {
+            Self::Ch1 => "C1",
+            Self::Ch2 => "C2",
+            Self::Ch3 => "C3",
+            Self::Ch4 => "C4",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdsCoupling {
+    Dc1M,
+    Ac1M,
+    Dc50,
+    Ac50,
+    Gnd,
+}
+
+impl Display for SdsCoupling {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Dc1M => "D1M",
+            Self::Ac1M => "A1M",
+            Self::Dc50 => "D50",
+            Self::Ac50 => "A50",
+            Self::Gnd => "GND",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdsTrigMode {
+    Auto,
+    Normal,
+    Single,
+    Stop,
+}
+
+impl Display for SdsTrigMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Auto => "AUTO",
+            Self::Normal => "NORM",
+            Self::Single => "SINGLE",
+            Self::Stop => "STOP",
+        })
+    }
+}
+
+impl FromStr for SdsTrigMode {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AUTO" => Ok(Self::Auto),
+            "NORM" => Ok(Self::Normal),
+            "SINGLE" => Ok(Self::Single),
+            "STOP" => Ok(Self::Stop),
+            x => Err(anyhow!("Unknown trigger mode {x}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdsSlope {
+    Rising,
+    Falling,
+    Window,
+}
+
+impl Display for SdsSlope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Rising => "POS",
+            Self::Falling => "NEG",
+            Self::Window => "WINDOW",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdsAcqState {
+    Stop,
+    Ready,
+    Armed,
+    Triggered,
+    Auto,
+}
+
+impl Display for SdsAcqState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Stop => "Stop",
+            Self::Ready => "Ready",
+            Self::Armed => "Arm",
+            Self::Triggered => "Trig'd",
+            Self::Auto => "Auto",
+        })
+    }
+}
+
+impl FromStr for SdsAcqState {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Stop" => Ok(Self::Stop),
+            "Ready" => Ok(Self::Ready),
+            "Arm" => Ok(Self::Armed),
+            "Trig'd" => Ok(Self::Triggered),
+            "Auto" => Ok(Self::Auto),
+            x => Err(anyhow!("Unknown acquisition state {x}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SdsImage {
+    Bmp,
+    Png,
+}
+
+impl Display for SdsImage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Bmp => "BMP",
+            Self::Png => "PNG",
+        })
+    }
+}
+
+impl SdsImage {
+    // catch a truncated or garbled transfer before it ends up in a file
+    fn check(&self, img: &[u8]) -> anyhow::Result<()> {
+        match *self {
+            // "BM" and the file size as u32 LE
+            Self::Bmp => {
+                if img.len() < 6 || &img[..2] != b"BM" {
+                    return Err(anyhow!("Not a BMP image"));
+                }
+                let size = u32::from_le_bytes([img[2], img[3], img[4], img[5]]) as usize;
+                if size != img.len() {
+                    return Err(anyhow!("BMP size {size}, got {} bytes", img.len()));
+                }
+            }
+            // the signature up front and the IEND chunk at the very end
+            Self::Png => {
+                if !img.starts_with(b"\x89PNG\r\n\x1a\n") {
+                    return Err(anyhow!("Not a PNG image"));
+                }
+                if !img.ends_with(b"IEND\xae\x42\x60\x82") {
+                    return Err(anyhow!("Truncated PNG image, {} bytes", img.len()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The scope echoes the command header and appends units with SI prefixes,
+// "C1:VDIV 5.00E-01V", "SARA 1.00GSa/s" -> 0.5, 1e9
+fn parse_value(resp: &str, unit: &str) -> anyhow::Result<f32> {
+    let v = resp
+        .split_whitespace()
+        .last()
+        .ok_or(anyhow!("Empty response"))?;
+    let v = v.strip_suffix(unit).unwrap_or(v);
+    let (num, mult) = match v.chars().last() {
+        Some('G') => (&v[..v.len() - 1], 1e9),
+        Some('M') => (&v[..v.len() - 1], 1e6),
+        Some('k') => (&v[..v.len() - 1], 1e3),
+        Some('m') => (&v[..v.len() - 1], 1e-3),
+        Some('u') => (&v[..v.len() - 1], 1e-6),
+        Some('n') => (&v[..v.len() - 1], 1e-9),
+        _ => (v, 1.0),
+    };
+    num.parse::<f32>()
+        .map(|n| n * mult)
+        .map_err(|e| anyhow!("Cannot parse {resp}: {e}"))
+}
+
+// the last word of the response, "TRMD AUTO" -> "AUTO"
+fn parse_word(resp: &str) -> anyhow::Result<&str> {
+    resp.split_whitespace()
+        .last()
+        .ok_or(anyhow!("Empty response"))
+}
+
+pub struct SDS {
+    pub lxi: BlockLxi,
+}
+
+impl SDS {
+    pub fn new<S, H>(name: S, host: H) -> anyhow::Result<Self>
+        where
+            S: AsRef<str>,
+            H: fmt::Display + AsRef<str> + ToSocketAddrs,
+            Self: Sized,
+    {
+        Ok(Self {
+            lxi: BlockLxi::new(name, host)?,
+        })
+    }
+
+    pub fn idn_q(&mut self) -> anyhow::Result<String> {
+        self.lxi.req("*IDN?")
+    }
+
+    pub fn trace(&mut self, c: SdsCh, state: PortState) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:TRA {state}", c.hdr()))
+    }
+    pub fn trace_q(&mut self, c: SdsCh) -> anyhow::Result<PortState> {
+        let resp = self.lxi.req(format!("{}:TRA?", c.hdr()))?;
+        Ok(match parse_word(&resp)? {
+            "ON" => PortState::On,
+            _ => PortState::Off,
+        })
+    }
+
+    // volts per division
+    pub fn vdiv(&mut self, c: SdsCh, v: f32) -> anyhow::Result<f32> {
+        if v <= 0.0 || !v.is_finite() {
+            return Err(anyhow!("Invalid V/div {v}"));
+        }
+        self.lxi.send(format!("{}:VDIV {v}V", c.hdr()))?;
+        Ok(v)
+    }
+    pub fn vdiv_q(&mut self, c: SdsCh) -> anyhow::Result<f32> {
+        parse_value(&self.lxi.req(format!("{}:VDIV?", c.hdr()))?, "V")
+    }
+    pub fn offset(&mut self, c: SdsCh, v: f32) -> anyhow::Result<f32> {
+        self.lxi.send(format!("{}:OFST {v}V", c.hdr()))?;
+        Ok(v)
+    }
+    pub fn offset_q(&mut self, c: SdsCh) -> anyhow::Result<f32> {
+        parse_value(&self.lxi.req(format!("{}:OFST?", c.hdr()))?, "V")
+    }
+    pub fn coupling(&mut self, c: SdsCh, cpl: SdsCoupling) -> anyhow::Result<()> {
+        self.lxi.send(format!("{}:CPL {cpl}", c.hdr()))
+    }
+    // probe attenuation, e.g. 10 for a 10x probe
+    pub fn probe(&mut self, c: SdsCh, attn: f32) -> anyhow::Result<f32> {
+        if attn <= 0.0 || !attn.is_finite() {
+            return Err(anyhow!("Invalid probe attenuation {attn}"));
+        }
+        self.lxi.send(format!("{}:ATTN {attn}", c.hdr()))?;
+        Ok(attn)
+    }
+
+    // seconds per division
+    pub fn tdiv(&mut self, t: f32) -> anyhow::Result<f32> {
+        if t <= 0.0 || !t.is_finite() {
+            return Err(anyhow!("Invalid s/div {t}"));
+        }
+        self.lxi.send(format!("TDIV {t}S"))?;
+        Ok(t)
+    }
+    pub fn tdiv_q(&mut self) -> anyhow::Result<f32> {
+        parse_value(&self.lxi.req("TDIV?")?, "S")
+    }
+    // horizontal position of the trigger point, positive moves it left
+    pub fn trg_delay(&mut self, t: f32) -> anyhow::Result<f32> {
+        self.lxi.send(format!("TRDL {t}S"))?;
+        Ok(t)
+    }
+    pub fn trg_delay_q(&mut self) -> anyhow::Result<f32> {
+        parse_value(&self.lxi.req("TRDL?")?, "S")
+    }
+    pub fn sample_rate_q(&mut self) -> anyhow::Result<f32> {
+        parse_value(&self.lxi.req("SARA?")?, "Sa/s")
+    }
+
+    pub fn trg_edge(&mut self, c: SdsCh, slope: SdsSlope, level: f32) -> anyhow::Result<()> {
+        self.lxi.send(format!("TRSE EDGE,SR,{},HT,OFF", c.hdr()))?;
+        self.lxi.send(format!("{}:TRSL {slope}", c.hdr()))?;
+        self.lxi.send(format!("{}:TRLV {level}V", c.hdr()))?;
+        Ok(())
+    }
+    pub fn trg_mode(&mut self, mode: SdsTrigMode) -> anyhow::Result<SdsTrigMode> {
+        self.lxi.send(format!("TRMD {mode}"))?;
+        Ok(mode)
+    }
+    pub fn trg_mode_q(&mut self) -> anyhow::Result<SdsTrigMode> {
+        SdsTrigMode::from_str(parse_word(&self.lxi.req("TRMD?")?)?)
+    }
+    pub fn trg_force(&mut self) -> anyhow::Result<()> {
+        self.lxi.send("FRTR")
+    }
+    pub fn acq_state_q(&mut self) -> anyhow::Result<SdsAcqState> {
+        SdsAcqState::from_str(parse_word(&self.lxi.req("SAST?")?)?)
+    }
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        self.trg_mode(SdsTrigMode::Auto)?;
+        Ok(())
+    }
+    pub fn stop(&mut self) -> anyhow::Result<()> {
+        self.lxi.send("STOP")
+    }
+
+    // Arm a single acquisition and wait until it has been captured.
+    // Use trg_force() to capture anyway if this times out.
+    pub fn single(&mut self, timeout: time::Duration) -> anyhow::Result<()> {
+        self.trg_mode(SdsTrigMode::Single)?;
+        // give the scope time to leave the stopped state
+        thread::sleep(ACQ_POLL);
+        let start = time::Instant::now();
+        while self.acq_state_q()? != SdsAcqState::Stop {
+            if start.elapsed() > timeout {
+                return Err(anyhow!("No trigger in {timeout:?}"));
+            }
+            thread::sleep(ACQ_POLL);
+        }
+        Ok(())
+    }
+
+    // Transfer the acquired trace of the channel, scaled to volts. The time of
+    // the first sample is relative to the trigger point.
+    pub fn wave_q(&mut self, c: SdsCh) -> anyhow::Result<Waveform> {
+        let vdiv = self.vdiv_q(c)?;
+        let offset = self.offset_q(c)?;
+        let tdiv = self.tdiv_q()?;
+        let delay = self.trg_delay_q()?;
+        let sara = self.sample_rate_q()?;
+        if sara <= 0.0 {
+            return Err(anyhow!("Invalid sample rate {sara}"));
+        }
+
+        // all points, no sparsing
+        self.lxi.send("WFSU SP,0,NP,0,FP,0")?;
+        let data = self.lxi.req_raw(format!("{}:WF? DAT2", c.hdr()))?;
+        let points = data
+            .iter()
+            .map(|b| *b as i8 as f32 * vdiv / CODES_PER_DIV - offset)
+            .collect::<Vec<f32>>();
+        Ok(Waveform::new(Meas::Volt, 1.0 / sara, points)?
+            .with_start(-delay - tdiv * GRID_X / 2.0))
+    }
+
+    // screen capture as an image file
+    pub fn screenshot<P: AsRef<Path>>(&mut self, path: P, fmt: SdsImage) -> anyhow::Result<usize> {
+        let path = path.as_ref();
+        let img = self.lxi.req_raw(format!("PRIN? {fmt}"))?;
+        fmt.check(&img)?;
+        fs::write(path, &img).map_err(|e| anyhow!("Cannot write {path:?}: {e}"))?;
+        info!("{}: {fmt} screenshot {} bytes to {path:?}", self.lxi.name(), img.len());
+        Ok(img.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= b.abs() * 1e-6
+    }
+
+    #[test]
+    fn value() {
+        assert_eq!(parse_value("C1:VDIV 5.00E-01V", "V").unwrap(), 0.5);
+        assert_eq!(parse_value("SARA 1.00GSa/s", "Sa/s").unwrap(), 1e9);
+        assert_eq!(parse_value("SARA 500MSa/s", "Sa/s").unwrap(), 5e8);
+        assert_eq!(parse_value("SARA 2.00kSa/s\n", "Sa/s").unwrap(), 2e3);
+        assert!(close(parse_value("TDIV 5.00mS", "S").unwrap(), 5e-3));
+        assert!(close(parse_value("TDIV 2.00uS", "S").unwrap(), 2e-6));
+        assert!(close(parse_value("TRDL 10.0nS", "S").unwrap(), 1e-8));
+        assert_eq!(parse_value("C1:OFST -1.20E+00V", "V").unwrap(), -1.2);
+        // no unit or prefix
+        assert_eq!(parse_value("SANU 1400", "pts").unwrap(), 1400.0);
+        assert!(parse_value("", "V").is_err());
+        assert!(parse_value("C1:VDIV ?V", "V").is_err());
+    }
+
+    #[test]
+    fn image_check() {
+        let mut bmp = b"BM\0\0\0\0rest of the bitmap".to_vec();
+        let len = bmp.len() as u32;
+        bmp[2..6].copy_from_slice(&len.to_le_bytes());
+        assert!(SdsImage::Bmp.check(&bmp).is_ok());
+        assert!(SdsImage::Bmp.check(&bmp[..bmp.len() - 1]).is_err());
+        assert!(SdsImage::Bmp.check(b"BM").is_err());
+        assert!(SdsImage::Png.check(&bmp).is_err());
+
+        let png = b"\x89PNG\r\n\x1a\n....\0\0\0\0IEND\xae\x42\x60\x82";
+        assert!(SdsImage::Png.check(png).is_ok());
+        assert!(SdsImage::Png.check(&png[..png.len() - 2]).is_err());
+        assert!(SdsImage::Bmp.check(png).is_err());
+        assert!(SdsImage::Png.check(b"").is_err());
+    }
+}
+
+// EOF
@@ -1,7 +1,7 @@
 // spd3303x.rs
 #![allow(dead_code)]
 
-use std::{fmt, fmt::Display, net::ToSocketAddrs, str::FromStr};
+use std::{fmt, fmt::Display, net::ToSocketAddrs, str::FromStr, thread, time};
 
 use anyhow::anyhow;
 use num::traits::Float;
@@ -10,14 +10,101 @@ use crate::*;
 
 // https://int.siglent.com/upload_file/user/SPD3000X/SPD3303X_QuickStart_QS0503X-E01B.pdf
 
-#[derive(Debug)]
+// CH1/CH2 are adjustable and measurable
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PwrCh {
+    Ch1,
+    Ch2,
+}
+
+impl From<PwrCh> for Ch {
+    fn from(c: PwrCh) -> Self {
+        match c {
+            PwrCh::Ch1 => Ch::Ch1,
+            PwrCh::Ch2 => Ch::Ch2,
+        }
+    }
+}
+
+impl Display for PwrCh {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Ch::from(*self).fmt(f)
+    }
+}
+
+// CH3 can only be switched on and off
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PwrOut {
+    Ch1,
+    Ch2,
+    Ch3,
+}
+
+impl From<PwrCh> for PwrOut {
+    fn from(c: PwrCh) -> Self {
+        match c {
+            PwrCh::Ch1 => PwrOut::Ch1,
+            PwrCh::Ch2 => PwrOut::Ch2,
+        }
+    }
+}
+
+impl From<PwrOut> for Ch {
+    fn from(c: PwrOut) -> Self {
+        match c {
+            PwrOut::Ch1 => Ch::Ch1,
+            PwrOut::Ch2 => Ch::Ch2,
+            PwrOut::Ch3 => Ch::Ch3,
+        }
+    }
+}
+
+impl Display for PwrOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Ch::from(*self).fmt(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PwrMeas {
+    Volt,
+    Curr,
+    Powr,
+}
+
+impl From<PwrMeas> for Meas {
+    fn from(m: PwrMeas) -> Self {
+        match m {
+            PwrMeas::Volt => Meas::Volt,
+            PwrMeas::Curr => Meas::Curr,
+            PwrMeas::Powr => Meas::Powr,
+        }
+    }
+}
+
+impl Display for PwrMeas {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Meas::from(*self).fmt(f)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PwrChannelMode {
     CV,
     // Constant voltage
     CC, // Constant current i.e. current limit reached
 }
 
-#[derive(Debug)]
+impl Display for PwrChannelMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::CV => "CV",
+            Self::CC => "CC",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PwrOutputMode {
     Invalid,
     Independent,
@@ -25,13 +112,54 @@ pub enum PwrOutputMode {
     Series,
 }
 
-#[derive(Debug)]
+impl Display for PwrOutputMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Invalid => "Invalid",
+            Self::Independent => "Independent",
+            Self::Parallel => "Parallel",
+            Self::Series => "Series",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PwrDisplayMode {
     Digital,
     Waveform,
 }
 
-#[derive(Debug)]
+impl Display for PwrDisplayMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::Digital => "Digital",
+            Self::Waveform => "Waveform",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PwrStatusChange {
+    OutputMode(PwrOutputMode, PwrOutputMode),
+    Output(PwrCh, PortState),
+    Mode(PwrCh, PwrChannelMode, PwrChannelMode),
+    Display(PwrCh, PwrDisplayMode),
+    Timer(PwrCh, PortState),
+}
+
+impl Display for PwrStatusChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::OutputMode(old, new) => write!(f, "mode {old}->{new}"),
+            Self::Output(c, st) => write!(f, "{c} output {st}"),
+            Self::Mode(c, old, new) => write!(f, "{c} {old}->{new}"),
+            Self::Display(c, d) => write!(f, "{c} display {d}"),
+            Self::Timer(c, st) => write!(f, "{c} timer {st}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct SPD3303XStatus {
     pub output_mode: PwrOutputMode,
     pub ch1: PortState,
@@ -95,6 +223,96 @@ impl SPD3303XStatus {
             },
         }
     }
+
+    pub fn to_u16(&self) -> u16 {
+        let mode: u16 = match self.output_mode {
+            PwrOutputMode::Independent => 0b01,
+            PwrOutputMode::Parallel => 0b10,
+            PwrOutputMode::Series => 0b11,
+            PwrOutputMode::Invalid => 0b00,
+        };
+        (mode << 2)
+            | bit(self.ch1_mode == PwrChannelMode::CC, 0)
+            | bit(self.ch2_mode == PwrChannelMode::CC, 1)
+            | bit(self.ch1 == PortState::On, 4)
+            | bit(self.ch2 == PortState::On, 5)
+            | bit(self.timer1 == PortState::On, 6)
+            | bit(self.timer2 == PortState::On, 7)
+            | bit(self.ch1_display == PwrDisplayMode::Waveform, 8)
+            | bit(self.ch2_display == PwrDisplayMode::Waveform, 9)
+    }
+
+    // output mode first, then output, CV/CC, display and timer of each channel
+    pub fn diff(&self, new: &Self) -> Vec<PwrStatusChange> {
+        let mut changes = Vec::new();
+        if self.output_mode != new.output_mode {
+            changes.push(PwrStatusChange::OutputMode(self.output_mode, new.output_mode));
+        }
+        for (c, old, new) in [
+            (PwrCh::Ch1, self.ch1, new.ch1),
+            (PwrCh::Ch2, self.ch2, new.ch2),
+        ] {
+            if old != new {
+                changes.push(PwrStatusChange::Output(c, new));
+            }
+        }
+        for (c, old, new) in [
+            (PwrCh::Ch1, self.ch1_mode, new.ch1_mode),
+            (PwrCh::Ch2, self.ch2_mode, new.ch2_mode),
+        ] {
+            if old != new {
+                changes.push(PwrStatusChange::Mode(c, old, new));
+            }
+        }
+        for (c, old, new) in [
+            (PwrCh::Ch1, self.ch1_display, new.ch1_display),
+            (PwrCh::Ch2, self.ch2_display, new.ch2_display),
+        ] {
+            if old != new {
+                changes.push(PwrStatusChange::Display(c, new));
+            }
+        }
+        for (c, old, new) in [
+            (PwrCh::Ch1, self.timer1, new.timer1),
+            (PwrCh::Ch2, self.timer2, new.timer2),
+        ] {
+            if old != new {
+                changes.push(PwrStatusChange::Timer(c, new));
+            }
+        }
+        changes
+    }
+
+    pub fn output(&self, c: PwrCh) -> PortState {
+        match c {
+            PwrCh::Ch1 => self.ch1,
+            PwrCh::Ch2 => self.ch2,
+        }
+    }
+    pub fn mode(&self, c: PwrCh) -> PwrChannelMode {
+        match c {
+            PwrCh::Ch1 => self.ch1_mode,
+            PwrCh::Ch2 => self.ch2_mode,
+        }
+    }
+}
+
+impl Display for SPD3303XStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "mode {}, CH1 {} {} {} timer {}, CH2 {} {} {} timer {}",
+            self.output_mode,
+            self.ch1,
+            self.ch1_mode,
+            self.ch1_display,
+            self.timer1,
+            self.ch2,
+            self.ch2_mode,
+            self.ch2_display,
+            self.timer2,
+        )
+    }
 }
 
 impl FromStr for SPD3303XStatus {
@@ -108,8 +326,97 @@ impl FromStr for SPD3303XStatus {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SPD3303XModel {
+    SPD3303X,
+    SPD3303XE,
+    SPD3303C,
+}
+
+impl SPD3303XModel {
+    pub fn limits(&self) -> SPD3303XLimits {
+        // CH1/CH2 ranges are the same across the family, only the resolution differs
+        match *self {
+            Self::SPD3303X => SPD3303XLimits {
+                volt_max: 32.0,
+                curr_max: 3.2,
+                volt_digits: 3,
+                curr_digits: 3,
+            },
+            Self::SPD3303XE => SPD3303XLimits {
+                volt_max: 32.0,
+                curr_max: 3.2,
+                volt_digits: 2,
+                curr_digits: 2,
+            },
+            Self::SPD3303C => SPD3303XLimits {
+                volt_max: 32.0,
+                curr_max: 3.2,
+                volt_digits: 2,
+                curr_digits: 2,
+            },
+        }
+    }
+}
+
+impl Display for SPD3303XModel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Self::SPD3303X => "SPD3303X",
+            Self::SPD3303XE => "SPD3303X-E",
+            Self::SPD3303C => "SPD3303C",
+        })
+    }
+}
+
+impl FromStr for SPD3303XModel {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "SPD3303X" => Ok(Self::SPD3303X),
+            "SPD3303X-E" => Ok(Self::SPD3303XE),
+            "SPD3303C" => Ok(Self::SPD3303C),
+            x => Err(anyhow!("Unknown model {x}")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SPD3303XLimits {
+    pub volt_max: f32,
+    pub curr_max: f32,
+    pub volt_digits: usize,
+    pub curr_digits: usize,
+}
+
+impl SPD3303XLimits {
+    pub fn volt_check(&self, val: f32) -> anyhow::Result<()> {
+        if val.is_nan() {
+            Err(anyhow!("Voltage is not a number."))
+        } else if val < 0.0 {
+            Err(anyhow!("Voltage {val} is negative."))
+        } else if val > self.volt_max {
+            Err(anyhow!("Voltage {val} too high, max={}", self.volt_max))
+        } else {
+            Ok(())
+        }
+    }
+    pub fn curr_check(&self, val: f32) -> anyhow::Result<()> {
+        if val.is_nan() {
+            Err(anyhow!("Current is not a number."))
+        } else if val < 0.0 {
+            Err(anyhow!("Current {val} is negative."))
+        } else if val > self.curr_max {
+            Err(anyhow!("Current {val} too high, max={}", self.curr_max))
+        } else {
+            Ok(())
+        }
+    }
+}
+
 pub struct SPD3303X {
     pub lxi: StdLxi,
+    pub model: SPD3303XModel,
 }
 
 impl SPD3303X {
@@ -119,9 +426,21 @@ impl SPD3303X {
             H: fmt::Display + AsRef<str> + ToSocketAddrs,
             Self: Sized,
     {
-        Ok(Self {
+        let mut pwr = Self {
             lxi: StdLxi::new(name, host)?,
-        })
+            model: SPD3303XModel::SPD3303X,
+        };
+        pwr.detect_model()?;
+        Ok(pwr)
+    }
+
+    pub fn detect_model(&mut self) -> anyhow::Result<SPD3303XModel> {
+        let idn = self.idn_q()?;
+        self.model = idn_model_or(&idn, self.model);
+        Ok(self.model)
+    }
+    pub fn limits(&self) -> SPD3303XLimits {
+        self.model.limits()
     }
 
     pub fn idn_q(&mut self) -> anyhow::Result<String> {
@@ -147,68 +466,97 @@ impl SPD3303X {
         self.lxi.req("GATE?")
     }
 
-    pub fn meas_q(&mut self, c: Ch, m: Meas) -> anyhow::Result<f32> {
-        match c {
-            Ch::Ch1 | Ch::Ch2 => {}
-            _ => {
-                return Err(anyhow!("Device cannot measure {c}"));
-            }
-        }
-        match m {
-            Meas::Volt | Meas::Curr | Meas::Powr => {}
-            _ => {
-                return Err(anyhow!("Device cannot measure {m}"));
-            }
-        }
-        let m = self.lxi.req(&format!("MEAS:{m}? {c}"))?;
+    pub fn meas_q(&mut self, c: PwrCh, m: PwrMeas) -> anyhow::Result<f32> {
+        let m = self.lxi.req(format!("MEAS:{m}? {c}"))?;
         Ok(m.parse::<f32>()?)
     }
-    pub fn volt_m(&mut self, c: Ch) -> anyhow::Result<f32> {
-        self.meas_q(c, Meas::Volt)
+    pub fn volt_m(&mut self, c: PwrCh) -> anyhow::Result<f32> {
+        self.meas_q(c, PwrMeas::Volt)
     }
-    pub fn curr_m(&mut self, c: Ch) -> anyhow::Result<f32> {
-        self.meas_q(c, Meas::Curr)
+    pub fn curr_m(&mut self, c: PwrCh) -> anyhow::Result<f32> {
+        self.meas_q(c, PwrMeas::Curr)
     }
-    pub fn powr_m(&mut self, c: Ch) -> anyhow::Result<f32> {
-        self.meas_q(c, Meas::Powr)
+    pub fn powr_m(&mut self, c: PwrCh) -> anyhow::Result<f32> {
+        self.meas_q(c, PwrMeas::Powr)
     }
 
-    pub fn volt<F>(&mut self, c: Ch, v: F) -> anyhow::Result<F>
+    pub fn volt<F>(&mut self, c: PwrCh, v: F) -> anyhow::Result<F>
         where
             F: Float + Display,
     {
-        self.lxi.set_f(format!("{c}:VOLT"), v)?;
+        let lim = self.limits();
+        lim.volt_check(v.to_f32().unwrap_or(f32::NAN))?;
+        self.lxi.send(format!("{c}:VOLT {v:.prec$}", prec = lim.volt_digits))?;
         Ok(v)
     }
-    pub fn curr<F>(&mut self, c: Ch, v: F) -> anyhow::Result<F>
+    pub fn curr<F>(&mut self, c: PwrCh, v: F) -> anyhow::Result<F>
         where
             F: Float + Display,
     {
-        self.lxi.set_f(format!("{c}:CURR"), v)?;
+        let lim = self.limits();
+        lim.curr_check(v.to_f32().unwrap_or(f32::NAN))?;
+        self.lxi.send(format!("{c}:CURR {v:.prec$}", prec = lim.curr_digits))?;
         Ok(v)
     }
 
-    fn param_q<S>(&mut self, c: Ch, param: S) -> anyhow::Result<f32>
+    fn param_q<S>(&mut self, c: PwrCh, param: S) -> anyhow::Result<f32>
         where
             S: AsRef<str>,
     {
-        match c {
-            Ch::Ch1 | Ch::Ch2 => {}
-            _ => {
-                return Err(anyhow!("Device cannot query {c}"));
-            }
-        }
-        let m = self.lxi.req(&format!("{c}:{}?", param.as_ref()))?;
+        let m = self.lxi.req(format!("{c}:{}?", param.as_ref()))?;
         Ok(m.parse::<f32>()?)
     }
 
-    pub fn volt_q(&mut self, c: Ch) -> anyhow::Result<f32> {
+    pub fn volt_q(&mut self, c: PwrCh) -> anyhow::Result<f32> {
         self.param_q(c, "VOLT")
     }
-    pub fn curr_q(&mut self, c: Ch) -> anyhow::Result<f32> {
+    pub fn curr_q(&mut self, c: PwrCh) -> anyhow::Result<f32> {
         self.param_q(c, "CURR")
     }
 
+    // Soft-start: move the setpoint(s) linearly from the current values to the target
+    // in the given number of steps, giving up if the channel falls into CC mode.
+    pub fn ramp(
+        &mut self,
+        c: PwrCh,
+        volt: f32,
+        curr: Option<f32>,
+        duration: time::Duration,
+        steps: u32,
+    ) -> anyhow::Result<()> {
+        if steps == 0 {
+            return Err(anyhow!("Ramp needs at least one step"));
+        }
+        let lim = self.limits();
+        lim.volt_check(volt)?;
+        if let Some(i) = curr {
+            lim.curr_check(i)?;
+        }
+
+        let volt_start = self.volt_q(c)?;
+        let curr_start = match curr {
+            Some(_) => self.curr_q(c)?,
+            None => 0.0,
+        };
+        let step_time = duration / steps;
+        debug!("Ramp {c}: {volt_start:.3}V -> {volt:.3}V in {steps} steps of {step_time:?}");
+
+        for n in 1..=steps {
+            let frac = n as f32 / steps as f32;
+            let v = volt_start + (volt - volt_start) * frac;
+            if let Some(i) = curr {
+                self.curr(c, curr_start + (i - curr_start) * frac)?;
+            }
+            self.volt(c, v)?;
+            thread::sleep(step_time);
+
+            if self.status_q()?.mode(c) == PwrChannelMode::CC {
+                return Err(anyhow!("Ramp aborted, {c} in CC mode at {v:.3}V"));
+            }
+        }
+        Ok(())
+    }
+
     pub fn output_independent(&mut self) -> anyhow::Result<()> {
         self.lxi.send("OUTPUT:TRACK 0")
     }
@@ -218,23 +566,75 @@ impl SPD3303X {
     pub fn output_parallel(&mut self) -> anyhow::Result<()> {
         self.lxi.send("OUTPUT:TRACK 2")
     }
-    pub fn wave_display(&mut self, c: Ch, mode: PortState) -> anyhow::Result<()> {
+    pub fn wave_display(&mut self, c: PwrCh, mode: PortState) -> anyhow::Result<()> {
         self.lxi.send(format!("OUTPUT:WAVE {c},{mode}"))
     }
-    pub fn output_state(&mut self, c: Ch, state: PortState) -> anyhow::Result<()> {
-        match c {
-            Ch::Ch1 | Ch::Ch2 | Ch::Ch3 => {}
-            _ => {
-                return Err(anyhow!("Device does not have output {c}"));
-            }
-        }
+    pub fn output_state<C>(&mut self, c: C, state: PortState) -> anyhow::Result<()>
+        where
+            C: Into<PwrOut>,
+    {
+        let c = c.into();
         self.lxi.send(format!("OUTPUT {c},{state}"))
     }
-    pub fn output_on(&mut self, c: Ch) -> anyhow::Result<()> {
+    pub fn output_on<C>(&mut self, c: C) -> anyhow::Result<()>
+        where
+            C: Into<PwrOut>,
+    {
         self.output_state(c, PortState::On)
     }
-    pub fn output_off(&mut self, c: Ch) -> anyhow::Result<()> {
+    pub fn output_off<C>(&mut self, c: C) -> anyhow::Result<()>
+        where
+            C: Into<PwrOut>,
+    {
         self.output_state(c, PortState::Off)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_round_trip() {
+        // mode bits 0b00 decode to Invalid and encode back the same way
+        for st in 0..0x400u16 {
+            let s = SPD3303XStatus::from_u16(st);
+            assert_eq!(SPD3303XStatus::from_u16(s.to_u16()), s);
+            assert_eq!(s.to_u16(), st);
+        }
+    }
+
+    #[test]
+    fn status_from_str() {
+        let s = SPD3303XStatus::from_str("0x0014").unwrap();
+        assert_eq!(s.output_mode, PwrOutputMode::Independent);
+        assert_eq!(s.output(PwrCh::Ch1), PortState::On);
+        assert_eq!(s.output(PwrCh::Ch2), PortState::Off);
+        assert!(SPD3303XStatus::from_str("0014").is_err());
+        assert!(SPD3303XStatus::from_str("0xzz").is_err());
+    }
+
+    #[test]
+    fn status_diff() {
+        // independent, CH1 off, both CV
+        let old = SPD3303XStatus::from_u16(0b0100);
+        assert!(old.diff(&old).is_empty());
+
+        let new = SPD3303XStatus::from_u16(0b0001_0101);
+        let changes = old
+            .diff(&new)
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(changes, ["CH1 output ON", "CH1 CV->CC"]);
+
+        let new = SPD3303XStatus::from_u16(0b10_1110);
+        let changes = old
+            .diff(&new)
+            .iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<String>>();
+        assert_eq!(changes, ["mode Independent->Series", "CH2 output ON", "CH2 CV->CC"]);
+    }
+}
 // EOF
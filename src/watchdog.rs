@@ -0,0 +1,376 @@
+// watchdog.rs
+#![allow(dead_code)]
+
+use std::{fmt, thread, time};
+
+use anyhow::anyhow;
+
+use crate::*;
+
+// Software protection for supplies that have no programmable OVP/OCP:
+// poll the measurements and status, switch the output off when a limit is exceeded.
+
+// run() gives up after this many polls in a row have failed
+const POLL_ERR_MAX: u32 = 3;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PwrLimits {
+    pub volt_max: Option<f32>,
+    pub curr_max: Option<f32>,
+    pub powr_max: Option<f32>,
+    // how long the channel may stay in CC mode
+    pub cc_max: Option<time::Duration>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum PwrTrip {
+    Volt(f32),
+    Curr(f32),
+    Powr(f32),
+    CcMode(time::Duration),
+    // the channel could not be measured
+    NoMeas,
+}
+
+impl fmt::Display for PwrTrip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Volt(v) => write!(f, "over-voltage {v:.3}V"),
+            Self::Curr(c) => write!(f, "over-current {c:.3}A"),
+            Self::Powr(p) => write!(f, "over-power {p:.2}W"),
+            Self::CcMode(d) => write!(f, "CC mode for {:.1}s", d.as_secs_f32()),
+            Self::NoMeas => f.write_str("measurement failed"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PwrTripEvent {
+    pub ch: PwrCh,
+    pub trip: PwrTrip,
+}
+
+impl fmt::Display for PwrTripEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.ch, self.trip)
+    }
+}
+
+struct Watched {
+    ch: PwrCh,
+    limits: PwrLimits,
+    cc_since: Option<time::Instant>,
+}
+
+pub struct PwrWatchdog {
+    pub interval: time::Duration,
+    watched: Vec<Watched>,
+}
+
+impl PwrWatchdog {
+    pub fn new(interval: time::Duration) -> Self {
+        Self {
+            interval,
+            watched: Vec::new(),
+        }
+    }
+
+    pub fn watch(&mut self, ch: PwrCh, limits: PwrLimits) {
+        self.watched.retain(|w| w.ch != ch);
+        self.watched.push(Watched {
+            ch,
+            limits,
+            cc_since: None,
+        });
+    }
+
+    // Check all watched channels once, switch off and report the ones that tripped.
+    // A channel that cannot be measured is switched off as well and the others
+    // are still checked. If an output cannot be switched off the error lists
+    // all trips of this poll.
+    pub fn poll(&mut self, pwr: &mut SPD3303X) -> anyhow::Result<Vec<PwrTripEvent>> {
+        let name = pwr.lxi.name().to_owned();
+        let status = pwr.status_q()?;
+        let now = time::Instant::now();
+        let mut events = Vec::new();
+        let mut off_err = None;
+
+        for w in self.watched.iter_mut() {
+            if status.output(w.ch) == PortState::Off {
+                w.cc_since = None;
+                continue;
+            }
+
+            let lim = w.limits;
+            let mut trip = None;
+            if status.mode(w.ch) == PwrChannelMode::CC {
+                let since = *w.cc_since.get_or_insert(now);
+                match lim.cc_max {
+                    Some(max) if now.duration_since(since) > max => {
+                        trip = Some(PwrTrip::CcMode(now.duration_since(since)));
+                    }
+                    _ => {}
+                }
+            } else {
+                w.cc_since = None;
+            }
+
+            if trip.is_none() {
+                trip = Self::meas_trip(pwr, w.ch, &lim).unwrap_or_else(|e| {
+                    error!("{name}: cannot measure {}: {e}", w.ch);
+                    Some(PwrTrip::NoMeas)
+                });
+            }
+
+            if let Some(trip) = trip {
+                w.cc_since = None;
+                let ev = PwrTripEvent { ch: w.ch, trip };
+                match pwr.output_off(w.ch) {
+                    Ok(()) => {
+                        error!("{name}: protection tripped, output off: {ev}");
+                    }
+                    Err(e) => {
+                        error!("{name}: protection tripped, output NOT off: {ev}: {e}");
+                        off_err.get_or_insert(e);
+                    }
+                }
+                events.push(ev);
+            }
+        }
+
+        match off_err {
+            None => Ok(events),
+            Some(e) => {
+                let trips = events.iter().map(|ev| ev.to_string()).collect::<Vec<_>>();
+                Err(e.context(format!("Output not off, tripped: {}", trips.join(", "))))
+            }
+        }
+    }
+
+    fn meas_trip(
+        pwr: &mut SPD3303X,
+        ch: PwrCh,
+        lim: &PwrLimits,
+    ) -> anyhow::Result<Option<PwrTrip>> {
+        if let Some(max) = lim.volt_max {
+            let volt = pwr.volt_m(ch)?;
+            if volt > max {
+                return Ok(Some(PwrTrip::Volt(volt)));
+            }
+        }
+        if let Some(max) = lim.curr_max {
+            let curr = pwr.curr_m(ch)?;
+            if curr > max {
+                return Ok(Some(PwrTrip::Curr(curr)));
+            }
+        }
+        if let Some(max) = lim.powr_max {
+            let powr = pwr.powr_m(ch)?;
+            if powr > max {
+                return Ok(Some(PwrTrip::Powr(powr)));
+            }
+        }
+        Ok(None)
+    }
+
+    // Poll at the configured interval until something trips. A failed poll is
+    // retried, after POLL_ERR_MAX failures in a row all watched outputs are
+    // switched off and the error is returned.
+    pub fn run(&mut self, pwr: &mut SPD3303X) -> anyhow::Result<Vec<PwrTripEvent>> {
+        let name = pwr.lxi.name().to_owned();
+        let mut errors = 0;
+        loop {
+            match self.poll(pwr) {
+                Ok(events) if !events.is_empty() => return Ok(events),
+                Ok(_) => errors = 0,
+                Err(e) => {
+                    errors += 1;
+                    error!("{name}: watchdog poll failed ({errors}/{POLL_ERR_MAX}): {e:#}");
+                    if errors >= POLL_ERR_MAX {
+                        for w in self.watched.iter() {
+                            if let Err(e) = pwr.output_off(w.ch) {
+                                error!("{name}: cannot switch {} off: {e}", w.ch);
+                            }
+                        }
+                        return Err(anyhow!("Watchdog stopped, outputs switched off: {e:#}"));
+                    }
+                }
+            }
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+
+    // CH1 and CH2 on, both CV, independent
+    const BOTH_ON: u16 = 0b11_0100;
+
+    #[derive(Default)]
+    struct Sim {
+        status: u16,
+        // canned replies, the last one of each is repeated
+        replies: HashMap<String, Vec<String>>,
+        cmds: Vec<String>,
+    }
+
+    // an SPD3303X on localhost, OUTPUT commands update the status
+    fn simulator(sim: Sim) -> (SPD3303X, Arc<Mutex<Sim>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let sim = Arc::new(Mutex::new(sim));
+        let state = sim.clone();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut out = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else { break };
+                let cmd = line.trim().to_string();
+                let mut sim = state.lock().unwrap();
+                sim.cmds.push(cmd.clone());
+                let resp = if let Some(r) = sim.replies.get_mut(&cmd) {
+                    Some(if r.len() > 1 {
+                        r.remove(0)
+                    } else {
+                        r[0].clone()
+                    })
+                } else if cmd == "*IDN?" {
+                    Some("Siglent Technologies,SPD3303X-E,SPD3XIDD4R5542,1.01.01.02.05,V3.0".into())
+                } else if cmd == "SYST:STAT?" {
+                    Some(format!("0x{:04x}", sim.status))
+                } else if cmd.starts_with("MEAS:") {
+                    Some("1.000".into())
+                } else if cmd.contains('?') {
+                    Some("0".into())
+                } else {
+                    match cmd.as_str() {
+                        "OUTPUT CH1,OFF" => sim.status &= !(1 << 4),
+                        "OUTPUT CH2,OFF" => sim.status &= !(1 << 5),
+                        _ => {}
+                    }
+                    None
+                };
+                if let Some(resp) = resp {
+                    out.write_all(format!("{resp}\n").as_bytes()).unwrap();
+                }
+            }
+        });
+        (SPD3303X::new("PWR", addr).unwrap(), sim)
+    }
+
+    fn reply(key: &str, r: &[&str]) -> (String, Vec<String>) {
+        (key.to_string(), r.iter().map(|s| s.to_string()).collect())
+    }
+
+    fn limits(curr_max: f32) -> PwrLimits {
+        PwrLimits {
+            curr_max: Some(curr_max),
+            ..PwrLimits::default()
+        }
+    }
+
+    // the OUTPUT commands seen so far, the query makes sure all of them have arrived
+    fn offs(pwr: &mut SPD3303X, sim: &Arc<Mutex<Sim>>) -> Vec<String> {
+        pwr.idn_q().unwrap();
+        let sim = sim.lock().unwrap();
+        sim.cmds
+            .iter()
+            .filter(|c| c.starts_with("OUTPUT"))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn poll_trip() {
+        let (mut pwr, sim) = simulator(Sim {
+            status: BOTH_ON,
+            replies: HashMap::from([reply("MEAS:CURR? CH2", &["2.500"])]),
+            ..Sim::default()
+        });
+        let mut wd = PwrWatchdog::new(time::Duration::from_millis(1));
+        wd.watch(PwrCh::Ch1, limits(2.0));
+        wd.watch(PwrCh::Ch2, limits(2.0));
+
+        let events = wd.poll(&mut pwr).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].to_string(), "CH2: over-current 2.500A");
+        assert_eq!(offs(&mut pwr, &sim), ["OUTPUT CH2,OFF"]);
+        // CH2 is off now
+        assert!(wd.poll(&mut pwr).unwrap().is_empty());
+    }
+
+    #[test]
+    fn poll_meas_error() {
+        // CH1 cannot be measured, CH2 is over the limit
+        let (mut pwr, sim) = simulator(Sim {
+            status: BOTH_ON,
+            replies: HashMap::from([
+                reply("MEAS:CURR? CH1", &["garbage"]),
+                reply("MEAS:CURR? CH2", &["2.500"]),
+            ]),
+            ..Sim::default()
+        });
+        let mut wd = PwrWatchdog::new(time::Duration::from_millis(1));
+        wd.watch(PwrCh::Ch1, limits(2.0));
+        wd.watch(PwrCh::Ch2, limits(2.0));
+
+        let events = wd.poll(&mut pwr).unwrap();
+        let events = events.iter().map(|ev| ev.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            ["CH1: measurement failed", "CH2: over-current 2.500A"]
+        );
+        assert_eq!(offs(&mut pwr, &sim), ["OUTPUT CH1,OFF", "OUTPUT CH2,OFF"]);
+    }
+
+    #[test]
+    fn run_retries() {
+        // a garbled status once, then CH1 goes over the limit
+        let (mut pwr, sim) = simulator(Sim {
+            status: BOTH_ON,
+            replies: HashMap::from([
+                reply("SYST:STAT?", &["?", "0x0034"]),
+                reply("MEAS:CURR? CH1", &["1.000", "1.000", "3.000"]),
+            ]),
+            ..Sim::default()
+        });
+        let mut wd = PwrWatchdog::new(time::Duration::from_millis(1));
+        wd.watch(PwrCh::Ch1, limits(2.0));
+        let events = wd.run(&mut pwr).unwrap();
+        assert_eq!(events[0].to_string(), "CH1: over-current 3.000A");
+        assert_eq!(offs(&mut pwr, &sim), ["OUTPUT CH1,OFF"]);
+    }
+
+    #[test]
+    fn run_gives_up() {
+        let (mut pwr, sim) = simulator(Sim {
+            status: BOTH_ON,
+            replies: HashMap::from([reply("SYST:STAT?", &["?"])]),
+            ..Sim::default()
+        });
+        let mut wd = PwrWatchdog::new(time::Duration::from_millis(1));
+        wd.watch(PwrCh::Ch1, limits(2.0));
+        wd.watch(PwrCh::Ch2, limits(2.0));
+        assert!(wd.run(&mut pwr).is_err());
+        let polls = sim
+            .lock()
+            .unwrap()
+            .cmds
+            .iter()
+            .filter(|c| *c == "SYST:STAT?")
+            .count();
+        assert_eq!(polls, POLL_ERR_MAX as usize);
+        assert_eq!(offs(&mut pwr, &sim), ["OUTPUT CH1,OFF", "OUTPUT CH2,OFF"]);
+    }
+}
+
+// EOF
@@ -0,0 +1,151 @@
+// waveform.rs
+#![allow(dead_code)]
+
+use std::fmt;
+
+use anyhow::anyhow;
+
+use crate::*;
+
+#[derive(Clone, Debug)]
+pub struct Waveform {
+    pub meas: Meas,
+    // seconds between samples
+    pub interval: f32,
+    // time of the first sample relative to the trigger, in seconds
+    pub start: f32,
+    pub points: Vec<f32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WaveStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub rms: f32,
+    pub pk_pk: f32,
+}
+
+impl fmt::Display for WaveStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "min {:.4} max {:.4} mean {:.4} rms {:.4} p-p {:.4}",
+            self.min, self.max, self.mean, self.rms, self.pk_pk
+        )
+    }
+}
+
+impl Waveform {
+    pub fn new(meas: Meas, interval: f32, points: Vec<f32>) -> anyhow::Result<Self> {
+        if points.is_empty() {
+            return Err(anyhow!("Empty {meas} waveform"));
+        }
+        if !(interval > 0.0 && interval.is_finite()) {
+            return Err(anyhow!("Invalid sample interval {interval}"));
+        }
+        Ok(Self {
+            meas,
+            interval,
+            start: 0.0,
+            points,
+        })
+    }
+
+    pub fn with_start(mut self, start: f32) -> Self {
+        self.start = start;
+        self
+    }
+
+    // comma separated list of values, any unparseable value is an error
+    pub fn parse(meas: Meas, interval: f32, data: &str) -> anyhow::Result<Self> {
+        let points = data
+            .trim()
+            .split(',')
+            .enumerate()
+            .map(|(i, x)| {
+                x.trim()
+                    .parse::<f32>()
+                    .map_err(|e| anyhow!("Invalid {meas} waveform point #{i} {x:?}: {e}"))
+            })
+            .collect::<anyhow::Result<Vec<f32>>>()?;
+        Self::new(meas, interval, points)
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+    pub fn duration(&self) -> f32 {
+        self.interval * self.points.len() as f32
+    }
+    pub fn timestamps(&self) -> Vec<f32> {
+        (0..self.points.len())
+            .map(|i| self.start + i as f32 * self.interval)
+            .collect()
+    }
+    // (time, value) pairs
+    pub fn samples(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.points
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (self.start + i as f32 * self.interval, *v))
+    }
+
+    pub fn stats(&self) -> WaveStats {
+        let n = self.points.len() as f32;
+        let (min, max, sum, sum_sq) = self.points.iter().fold(
+            (f32::MAX, f32::MIN, 0.0, 0.0),
+            |(min, max, sum, sum_sq), v| (min.min(*v), max.max(*v), sum + v, sum_sq + v * v),
+        );
+        WaveStats {
+            min,
+            max,
+            mean: sum / n,
+            rms: (sum_sq / n).sqrt(),
+            pk_pk: max - min,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let w = Waveform::parse(Meas::Curr, 0.001, "1.0, 2.0,3.0\n").unwrap();
+        assert_eq!(w.points, [1.0, 2.0, 3.0]);
+        assert_eq!(w.len(), 3);
+        assert_eq!(w.duration(), 0.003);
+        assert_eq!(w.timestamps(), [0.0, 0.001, 0.002]);
+
+        assert!(Waveform::parse(Meas::Curr, 0.001, "").is_err());
+        assert!(Waveform::parse(Meas::Curr, 0.001, "1.0,x,3.0").is_err());
+        assert!(Waveform::parse(Meas::Curr, 0.0, "1.0").is_err());
+        assert!(Waveform::parse(Meas::Curr, f32::NAN, "1.0").is_err());
+    }
+
+    #[test]
+    fn start_time() {
+        let w = Waveform::new(Meas::Volt, 0.5, vec![1.0, 2.0])
+            .unwrap()
+            .with_start(-1.0);
+        assert_eq!(w.samples().collect::<Vec<_>>(), [(-1.0, 1.0), (-0.5, 2.0)]);
+    }
+
+    #[test]
+    fn stats() {
+        let w = Waveform::new(Meas::Volt, 1.0, vec![1.0, -1.0, 1.0, -1.0]).unwrap();
+        let s = w.stats();
+        assert_eq!(s.min, -1.0);
+        assert_eq!(s.max, 1.0);
+        assert_eq!(s.mean, 0.0);
+        assert_eq!(s.rms, 1.0);
+        assert_eq!(s.pk_pk, 2.0);
+    }
+}
+
+// EOF